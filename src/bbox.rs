@@ -1,6 +1,6 @@
 use core::f32;
 
-use crate::ray::{Interval, Point3, Ray};
+use crate::ray::{EMPTY, Interval, Point3};
 
 #[derive(Default, Clone, Copy)]
 pub struct Bbox {
@@ -14,6 +14,12 @@ impl Bbox {
         }
     }
 
+    pub fn empty() -> Bbox {
+        Bbox {
+            axis_intervals: [EMPTY; 3],
+        }
+    }
+
     pub fn from_points(a: Point3, b: Point3, c: Point3) -> Bbox {
         let mut bbox = Bbox::default();
 
@@ -36,24 +42,33 @@ impl Bbox {
         self.axis_intervals[i]
     }
 
-    pub fn intersects(&self, ray: Ray) -> bool {
-        let mut tmin: f32 = 0.0;
-        let mut tmax = f32::INFINITY;
-
-        for i in 0..=2 {
-            let dir_inv = 1.0 / ray.dir().axis_val(i);
+    pub fn union(&self, other: Bbox) -> Bbox {
+        Bbox {
+            axis_intervals: [
+                self.axis_intervals[0].union(other.axis_intervals[0]),
+                self.axis_intervals[1].union(other.axis_intervals[1]),
+                self.axis_intervals[2].union(other.axis_intervals[2]),
+            ],
+        }
+    }
 
-            let sign = f32::is_sign_negative(dir_inv); // same as signbit in C++
-            let bmin = self.axis_interval(i).get_val(sign as usize);
-            let bmax = self.axis_interval(i).get_val(!sign as usize);
+    pub fn centroid(&self) -> Point3 {
+        Point3::new(
+            0.5 * (self.axis_intervals[0].get_val(0) + self.axis_intervals[0].get_val(1)),
+            0.5 * (self.axis_intervals[1].get_val(0) + self.axis_intervals[1].get_val(1)),
+            0.5 * (self.axis_intervals[2].get_val(0) + self.axis_intervals[2].get_val(1)),
+        )
+    }
 
-            let dmin = (bmin - ray.origin().axis_val(i)) * dir_inv;
-            let dmax = (bmax - ray.origin().axis_val(i)) * dir_inv;
+    pub fn surface_area(&self) -> f32 {
+        let dx = self.axis_intervals[0].size();
+        let dy = self.axis_intervals[1].size();
+        let dz = self.axis_intervals[2].size();
 
-            tmin = f32::max(tmin, dmin);
-            tmax = f32::min(tmax, dmax);
+        if dx < 0.0 || dy < 0.0 || dz < 0.0 {
+            return 0.0;
         }
 
-        tmax > tmin
+        2.0 * (dx * dy + dy * dz + dz * dx)
     }
 }
@@ -15,7 +15,7 @@ impl BmpCanvas {
     pub fn new(width: u32, height: u32) -> BmpCanvas {
         // We pre-allocate the padding, this makes it easy to save the image to a bmp file later
         let mut scanline_size = width * 3;
-        let padding_size = scanline_size % 4;
+        let padding_size = (4 - scanline_size % 4) % 4;
         scanline_size += padding_size;
 
         let pixels = vec![0; scanline_size as usize * height as usize].into_boxed_slice();
@@ -49,7 +49,7 @@ impl Canvas for BmpCanvas {
     }
 
     fn get_pixel(&self, x: u32, y: u32) -> Pixel {
-        let idx = ((y * self.scanline_size) + x * 3) as usize;
+        let idx = (((self.height - 1 - y) * self.scanline_size) + x * 3) as usize;
 
         Pixel {
             b: self.pixels[idx], 
@@ -0,0 +1,72 @@
+// How surfaces scatter light, independent of where they are in the scene.
+//
+// Everything here works in a local shading frame: the surface's outward facing normal is +z,
+// so directions with a negative z are inside or behind the surface. `wo` points back along the
+// incoming ray and `wi` towards where light arrives from, both are unit vectors.
+
+use crate::vec3::Vec3;
+
+pub trait Bsdf {
+    // The BSDF times |cos| of `wi` for light arriving from `wi` and leaving towards `wo`,
+    // zero for specular lobes
+    fn eval(&self, wo: Vec3, wi: Vec3) -> Vec3;
+
+    // Picks `wi` for light leaving towards `wo`. `uc` chooses between lobes and `u` a direction
+    // within the lobe, all uniform in [0, 1). None if the path is absorbed
+    fn sample(&self, wo: Vec3, uc: f32, u: [f32; 2]) -> Option<BsdfSample>;
+
+    // Density per solid angle with which `sample` returns `wi`, zero for specular lobes
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f32;
+
+    // Whether every lobe is specular, so there's no point in sampling lights for the surface
+    fn is_specular(&self) -> bool;
+}
+
+pub struct BsdfSample {
+    pub wi: Vec3,
+    // eval / pdf, what the path's throughput gets multiplied by
+    pub weight: Vec3,
+    // Per solid angle, or for specular lobes the probability of having picked the lobe
+    pub pdf: f32,
+    pub specular: bool,
+}
+
+// Orthonormal basis around a unit normal, for moving between world space and the shading frame
+pub struct Frame {
+    tangent: Vec3,
+    bitangent: Vec3,
+    normal: Vec3,
+}
+
+impl Frame {
+    pub fn new(normal: Vec3) -> Frame {
+        let (tangent, bitangent) = normal.orthonormal_basis();
+
+        Frame {
+            tangent,
+            bitangent,
+            normal,
+        }
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            v.dot(self.tangent),
+            v.dot(self.bitangent),
+            v.dot(self.normal),
+        )
+    }
+
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x * self.tangent + v.y * self.bitangent + v.z * self.normal
+    }
+}
+
+// Mirrors a direction about the normal
+pub fn reflect(w: Vec3) -> Vec3 {
+    Vec3::new(-w.x, -w.y, w.z)
+}
+
+pub fn same_hemisphere(wo: Vec3, wi: Vec3) -> bool {
+    wo.z * wi.z > 0.0
+}
@@ -1,5 +1,20 @@
-use std::simd;
+use std::simd::{self, cmp::SimdPartialOrd, num::SimdFloat};
 
+use crate::{
+    bbox::Bbox,
+    ray::{Interval, Ray},
+    vec3::Vec3,
+};
+
+const BIN_COUNT: usize = 16;
+const MAX_LEAF_SIZE: usize = 8;
+
+// Relative cost of visiting a node compared to intersecting a primitive
+const TRAVERSAL_COST: f32 = 1.0;
+
+// Bounds of the 4 children of every node, stored as structure of arrays so one node can be
+// tested against a ray in a single pass. Unused child slots hold an empty box that never hits.
+#[derive(Default)]
 struct BvhData {
     x_min: Vec<simd::f32x4>,
     x_max: Vec<simd::f32x4>,
@@ -9,6 +24,583 @@ struct BvhData {
     z_max: Vec<simd::f32x4>,
 }
 
+// A child with a count of 0 is an inner node, `index` is then the node it points to.
+// Otherwise it is a leaf covering `prim_indices[index..index + count]`
+#[derive(Default, Clone, Copy)]
+struct ChildRef {
+    index: u32,
+    count: u32,
+}
+
+#[derive(Default)]
 pub struct BvhTree {
     node_data: BvhData,
+    children: Vec<[ChildRef; 4]>,
+    prim_indices: Vec<u32>,
+}
+
+// Intermediate binary tree, collapsed into the 4 wide tree once built
+struct BuildNode {
+    bbox: Bbox,
+    start: usize,
+    count: usize,
+    children: Option<[usize; 2]>,
+}
+
+impl BvhTree {
+    // Builds a tree over the given primitive bounds, hits report the index into `prim_bounds`
+    pub fn new(prim_bounds: &[Bbox]) -> BvhTree {
+        let mut tree = BvhTree::default();
+
+        if prim_bounds.is_empty() {
+            return tree;
+        }
+
+        let centroids: Vec<Vec3> = prim_bounds.iter().map(|bbox| bbox.centroid()).collect();
+        let mut prim_indices: Vec<u32> = (0..prim_bounds.len() as u32).collect();
+        let mut build_nodes: Vec<BuildNode> = Vec::with_capacity(2 * prim_bounds.len());
+
+        build_binary(
+            prim_bounds,
+            &centroids,
+            &mut prim_indices,
+            0,
+            prim_bounds.len(),
+            &mut build_nodes,
+        );
+
+        tree.prim_indices = prim_indices;
+
+        if build_nodes[0].children.is_none() {
+            // The whole tree fits in one leaf, give it a root node to live in
+            let node = tree.push_node();
+            tree.set_child(
+                node,
+                0,
+                build_nodes[0].bbox,
+                ChildRef {
+                    index: 0,
+                    count: build_nodes[0].count as u32,
+                },
+            );
+        } else {
+            tree.collapse(&build_nodes, 0);
+        }
+
+        tree
+    }
+
+    // Walks the tree front to back, calling `hit_primitive` for every primitive whose bounds the
+    // ray enters. `hit_primitive` returns the distance of its hit, which then shortens the ray
+    pub fn traverse(
+        &self,
+        ray: Ray,
+        interval: Interval,
+        mut hit_primitive: impl FnMut(usize, Interval) -> Option<f32>,
+    ) -> bool {
+        if self.children.is_empty() {
+            return false;
+        }
+
+        let origin = [
+            simd::f32x4::splat(ray.origin().x),
+            simd::f32x4::splat(ray.origin().y),
+            simd::f32x4::splat(ray.origin().z),
+        ];
+        let dir_inv = [
+            simd::f32x4::splat(1.0 / ray.dir().x),
+            simd::f32x4::splat(1.0 / ray.dir().y),
+            simd::f32x4::splat(1.0 / ray.dir().z),
+        ];
+        let signs = [
+            ray.dir().x.is_sign_negative(),
+            ray.dir().y.is_sign_negative(),
+            ray.dir().z.is_sign_negative(),
+        ];
+
+        let t_min = interval.get_val(0);
+        let mut t_max = interval.get_val(1);
+        let mut hit = false;
+
+        let mut stack: Vec<(f32, ChildRef)> = Vec::with_capacity(64);
+        stack.push((t_min, ChildRef { index: 0, count: 0 }));
+
+        while let Some((t_enter, child)) = stack.pop() {
+            if t_enter > t_max {
+                continue;
+            }
+
+            if child.count > 0 {
+                let start = child.index as usize;
+                for &prim in &self.prim_indices[start..start + child.count as usize] {
+                    if let Some(t) = hit_primitive(prim as usize, Interval::new(t_min, t_max)) {
+                        t_max = t;
+                        hit = true;
+                    }
+                }
+                continue;
+            }
+
+            let node = child.index as usize;
+            let bounds = [
+                [self.node_data.x_min[node], self.node_data.x_max[node]],
+                [self.node_data.y_min[node], self.node_data.y_max[node]],
+                [self.node_data.z_min[node], self.node_data.z_max[node]],
+            ];
+
+            let mut t_near = simd::f32x4::splat(t_min);
+            let mut t_far = simd::f32x4::splat(t_max);
+
+            for i in 0..=2 {
+                let bmin = bounds[i][signs[i] as usize];
+                let bmax = bounds[i][!signs[i] as usize];
+
+                t_near = t_near.simd_max((bmin - origin[i]) * dir_inv[i]);
+                t_far = t_far.simd_min((bmax - origin[i]) * dir_inv[i]);
+            }
+
+            let mut mask = t_near.simd_le(t_far).to_bitmask();
+            if mask == 0 {
+                continue;
+            }
+
+            // Push the farthest children first so the nearest one is visited next
+            let t_near = t_near.to_array();
+            let mut hits: [(f32, ChildRef); 4] = Default::default();
+            let mut hit_count = 0;
+            while mask != 0 {
+                let slot = mask.trailing_zeros() as usize;
+                hits[hit_count] = (t_near[slot], self.children[node][slot]);
+                hit_count += 1;
+                mask &= mask - 1;
+            }
+
+            let hits = &mut hits[..hit_count];
+            hits.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+            stack.extend_from_slice(hits);
+        }
+
+        hit
+    }
+
+    fn push_node(&mut self) -> usize {
+        let empty = Bbox::empty();
+        let min = |axis: usize| simd::f32x4::splat(empty.axis_interval(axis).get_val(0));
+        let max = |axis: usize| simd::f32x4::splat(empty.axis_interval(axis).get_val(1));
+
+        self.node_data.x_min.push(min(0));
+        self.node_data.x_max.push(max(0));
+        self.node_data.y_min.push(min(1));
+        self.node_data.y_max.push(max(1));
+        self.node_data.z_min.push(min(2));
+        self.node_data.z_max.push(max(2));
+        self.children.push([ChildRef::default(); 4]);
+
+        self.children.len() - 1
+    }
+
+    fn set_child(&mut self, node: usize, slot: usize, bbox: Bbox, child: ChildRef) {
+        self.node_data.x_min[node].as_mut_array()[slot] = bbox.axis_interval(0).get_val(0);
+        self.node_data.x_max[node].as_mut_array()[slot] = bbox.axis_interval(0).get_val(1);
+        self.node_data.y_min[node].as_mut_array()[slot] = bbox.axis_interval(1).get_val(0);
+        self.node_data.y_max[node].as_mut_array()[slot] = bbox.axis_interval(1).get_val(1);
+        self.node_data.z_min[node].as_mut_array()[slot] = bbox.axis_interval(2).get_val(0);
+        self.node_data.z_max[node].as_mut_array()[slot] = bbox.axis_interval(2).get_val(1);
+        self.children[node][slot] = child;
+    }
+
+    // Turns the binary node at `build_idx` into a 4 wide node by repeatedly opening up the
+    // largest inner child, returns the index of the new node
+    fn collapse(&mut self, build_nodes: &[BuildNode], build_idx: usize) -> usize {
+        let mut open: Vec<usize> = build_nodes[build_idx].children.unwrap().to_vec();
+
+        while open.len() < 4 {
+            let largest = open
+                .iter()
+                .enumerate()
+                .filter(|(_, idx)| build_nodes[**idx].children.is_some())
+                .max_by(|(_, a), (_, b)| {
+                    let area_a = build_nodes[**a].bbox.surface_area();
+                    let area_b = build_nodes[**b].bbox.surface_area();
+                    area_a.total_cmp(&area_b)
+                })
+                .map(|(i, _)| i);
+
+            match largest {
+                Some(i) => {
+                    let [left, right] = build_nodes[open[i]].children.unwrap();
+                    open[i] = left;
+                    open.push(right);
+                }
+                None => break,
+            }
+        }
+
+        let node = self.push_node();
+
+        for (slot, &child_idx) in open.iter().enumerate() {
+            let child = &build_nodes[child_idx];
+            let child_ref = match child.children {
+                Some(_) => ChildRef {
+                    index: self.collapse(build_nodes, child_idx) as u32,
+                    count: 0,
+                },
+                None => ChildRef {
+                    index: child.start as u32,
+                    count: child.count as u32,
+                },
+            };
+            self.set_child(node, slot, child.bbox, child_ref);
+        }
+
+        node
+    }
+}
+
+// Recursively splits prim_indices[start..start + count] using a binned SAH, pushing the nodes
+// to `build_nodes` with children always after their parent. Returns the index of the new node
+fn build_binary(
+    prim_bounds: &[Bbox],
+    centroids: &[Vec3],
+    prim_indices: &mut [u32],
+    start: usize,
+    count: usize,
+    build_nodes: &mut Vec<BuildNode>,
+) -> usize {
+    let prims = &mut prim_indices[start..start + count];
+
+    let mut bbox = Bbox::empty();
+    let mut centroid_bbox = Bbox::empty();
+    for &prim in prims.iter() {
+        bbox = bbox.union(prim_bounds[prim as usize]);
+        let centroid = centroids[prim as usize];
+        centroid_bbox = centroid_bbox.union(Bbox::from_points(centroid, centroid, centroid));
+    }
+
+    let node_idx = build_nodes.len();
+    build_nodes.push(BuildNode {
+        bbox,
+        start,
+        count,
+        children: None,
+    });
+
+    if count <= 2 {
+        return node_idx;
+    }
+
+    let split = find_split(prim_bounds, centroids, prims, bbox, centroid_bbox);
+
+    let mid = match split {
+        Some((axis, bin)) => {
+            let c_min = centroid_bbox.axis_interval(axis).get_val(0);
+            let c_size = centroid_bbox.axis_interval(axis).size();
+            partition(prims, |prim| {
+                bin_index(centroids[prim as usize].axis_val(axis), c_min, c_size) <= bin
+            })
+        }
+        None if count > MAX_LEAF_SIZE => count / 2,
+        None => return node_idx,
+    };
+
+    if split.is_none() {
+        // Every centroid is in the same spot, or the SAH prefers a leaf that is too big,
+        // split down the middle of the largest axis instead
+        let axis = largest_axis(centroid_bbox);
+        prims.sort_unstable_by(|a, b| {
+            let ca = centroids[*a as usize].axis_val(axis);
+            let cb = centroids[*b as usize].axis_val(axis);
+            ca.total_cmp(&cb)
+        });
+    }
+
+    let left = build_binary(
+        prim_bounds,
+        centroids,
+        prim_indices,
+        start,
+        mid,
+        build_nodes,
+    );
+    let right = build_binary(
+        prim_bounds,
+        centroids,
+        prim_indices,
+        start + mid,
+        count - mid,
+        build_nodes,
+    );
+
+    build_nodes[node_idx].children = Some([left, right]);
+
+    node_idx
+}
+
+// Returns the axis and last bin of the left side for the cheapest split,
+// or None if a leaf is cheaper than any split
+fn find_split(
+    prim_bounds: &[Bbox],
+    centroids: &[Vec3],
+    prims: &[u32],
+    bbox: Bbox,
+    centroid_bbox: Bbox,
+) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize)> = None;
+    let mut best_cost = prims.len() as f32;
+    let parent_area = bbox.surface_area();
+
+    if parent_area <= 0.0 {
+        return None;
+    }
+
+    for axis in 0..=2 {
+        let c_min = centroid_bbox.axis_interval(axis).get_val(0);
+        let c_size = centroid_bbox.axis_interval(axis).size();
+
+        if c_size <= 0.0 {
+            continue;
+        }
+
+        let mut bin_bounds = [Bbox::empty(); BIN_COUNT];
+        let mut bin_counts = [0usize; BIN_COUNT];
+
+        for &prim in prims {
+            let bin = bin_index(centroids[prim as usize].axis_val(axis), c_min, c_size);
+            bin_bounds[bin] = bin_bounds[bin].union(prim_bounds[prim as usize]);
+            bin_counts[bin] += 1;
+        }
+
+        // Sweep from the right to get the cost of everything after each split plane
+        let mut right_areas = [0.0; BIN_COUNT];
+        let mut right_counts = [0usize; BIN_COUNT];
+        let mut acc_bbox = Bbox::empty();
+        let mut acc_count = 0;
+        for bin in (1..BIN_COUNT).rev() {
+            acc_bbox = acc_bbox.union(bin_bounds[bin]);
+            acc_count += bin_counts[bin];
+            right_areas[bin] = acc_bbox.surface_area();
+            right_counts[bin] = acc_count;
+        }
+
+        let mut acc_bbox = Bbox::empty();
+        let mut acc_count = 0;
+        for bin in 0..BIN_COUNT - 1 {
+            acc_bbox = acc_bbox.union(bin_bounds[bin]);
+            acc_count += bin_counts[bin];
+
+            if acc_count == 0 || right_counts[bin + 1] == 0 {
+                continue;
+            }
+
+            let cost = TRAVERSAL_COST
+                + (acc_bbox.surface_area() * acc_count as f32
+                    + right_areas[bin + 1] * right_counts[bin + 1] as f32)
+                    / parent_area;
+
+            if cost < best_cost {
+                best_cost = cost;
+                best = Some((axis, bin));
+            }
+        }
+    }
+
+    best
+}
+
+fn bin_index(centroid: f32, c_min: f32, c_size: f32) -> usize {
+    let bin = ((centroid - c_min) / c_size * BIN_COUNT as f32) as usize;
+    usize::min(bin, BIN_COUNT - 1)
+}
+
+fn largest_axis(bbox: Bbox) -> usize {
+    let sizes = [
+        bbox.axis_interval(0).size(),
+        bbox.axis_interval(1).size(),
+        bbox.axis_interval(2).size(),
+    ];
+
+    if sizes[0] >= sizes[1] && sizes[0] >= sizes[2] {
+        0
+    } else if sizes[1] >= sizes[2] {
+        1
+    } else {
+        2
+    }
+}
+
+// Moves everything matching `pred` to the front, returns how many matched
+fn partition(prims: &mut [u32], pred: impl Fn(u32) -> bool) -> usize {
+    let mut mid = 0;
+    for i in 0..prims.len() {
+        if pred(prims[i]) {
+            prims.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{ray::Point3, vec3::Vec3};
+
+    // Möller-Trumbore, the distance along `ray` if it hits the triangle within `interval`
+    fn intersect(triangle: [Point3; 3], ray: Ray, interval: Interval) -> Option<f32> {
+        let [a, b, c] = triangle;
+        let (e1, e2) = (b - a, c - a);
+        let p = ray.dir().cross(e2);
+        let det = e1.dot(p);
+        if det == 0.0 {
+            return None;
+        }
+
+        let s = ray.origin() - a;
+        let u = s.dot(p) / det;
+        let q = s.cross(e1);
+        let v = ray.dir().dot(q) / det;
+        let t = e2.dot(q) / det;
+
+        (u >= 0.0 && v >= 0.0 && u + v <= 1.0 && interval.contains(t)).then_some(t)
+    }
+
+    // The closest hit through the tree has to be the closest of all triangles
+    fn assert_matches_brute_force(triangles: &[[Point3; 3]], rays: &[Ray]) {
+        let bounds: Vec<Bbox> = triangles
+            .iter()
+            .map(|&[a, b, c]| Bbox::from_points(a, b, c))
+            .collect();
+        let tree = BvhTree::new(&bounds);
+        let interval = Interval::new(0.001, f32::INFINITY);
+
+        for &ray in rays {
+            let expected = triangles
+                .iter()
+                .filter_map(|&triangle| intersect(triangle, ray, interval))
+                .min_by(f32::total_cmp);
+
+            let mut closest = None;
+            let hit = tree.traverse(ray, interval, |prim, interval| {
+                let t = intersect(triangles[prim], ray, interval)?;
+                closest = Some(t);
+                Some(t)
+            });
+
+            assert_eq!(hit, expected.is_some(), "{ray:?}");
+            assert_eq!(closest, expected, "{ray:?}");
+        }
+    }
+
+    fn random_point(rng: &mut StdRng, size: f32) -> Point3 {
+        Point3::new(
+            rng.random_range(-size..size),
+            rng.random_range(-size..size),
+            rng.random_range(-size..size),
+        )
+    }
+
+    // Random rays from around the scene, plus rays along the axes and the planes between them,
+    // which have zero direction components
+    fn rays(rng: &mut StdRng) -> Vec<Ray> {
+        let mut rays = Vec::new();
+        for _ in 0..500 {
+            let origin = random_point(rng, 3.0);
+            let target = random_point(rng, 1.0);
+            rays.push(Ray::new(origin, target - origin));
+        }
+
+        let axis_dirs = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 1.0),
+        ];
+        for dir in axis_dirs {
+            for _ in 0..100 {
+                let origin = random_point(rng, 1.0) - 2.0 * dir;
+                rays.push(Ray::new(origin, dir));
+            }
+        }
+
+        rays
+    }
+
+    fn random_triangles(rng: &mut StdRng, count: usize) -> Vec<[Point3; 3]> {
+        (0..count)
+            .map(|_| {
+                let center = random_point(rng, 1.0);
+                [(); 3].map(|_| center + random_point(rng, 0.3))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_brute_force_for_few_triangles() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for count in 1..=3 {
+            let triangles = random_triangles(&mut rng, count);
+            assert_matches_brute_force(&triangles, &rays(&mut rng));
+        }
+    }
+
+    #[test]
+    fn matches_brute_force_for_many_triangles() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let triangles = random_triangles(&mut rng, 1000);
+        assert_matches_brute_force(&triangles, &rays(&mut rng));
+    }
+
+    #[test]
+    fn matches_brute_force_with_shared_centroids() {
+        // Every triangle has its centroid at the origin, so no split can separate them by
+        // centroid
+        let mut rng = StdRng::seed_from_u64(3);
+        let triangles: Vec<[Point3; 3]> = (0..100)
+            .map(|_| {
+                let a = random_point(&mut rng, 1.0);
+                let b = random_point(&mut rng, 1.0);
+                [a, b, -(a + b)]
+            })
+            .collect();
+        assert_matches_brute_force(&triangles, &rays(&mut rng));
+    }
+
+    #[test]
+    fn matches_brute_force_for_flat_axis_aligned_triangles() {
+        // Boxes with no thickness along one axis, hit by rays travelling inside their plane
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut triangles = Vec::new();
+        for axis in 0..3 {
+            for _ in 0..50 {
+                let level = rng.random_range(-1.0..1.0);
+                let flatten = |p: Point3| match axis {
+                    0 => Point3::new(level, p.y, p.z),
+                    1 => Point3::new(p.x, level, p.z),
+                    _ => Point3::new(p.x, p.y, level),
+                };
+                let center = random_point(&mut rng, 1.0);
+                triangles.push([(); 3].map(|_| flatten(center + random_point(&mut rng, 0.3))));
+            }
+        }
+
+        // Rays starting level with the edge of a box, where a zero direction component puts a
+        // 0 * infinity into the slab test
+        let mut rays = rays(&mut rng);
+        for (i, &[a, b, _]) in triangles.iter().enumerate() {
+            let axis = i / 50;
+            let mut dir = Vec3::default();
+            match axis {
+                0 => dir.x = -1.0,
+                1 => dir.y = -1.0,
+                _ => dir.z = -1.0,
+            }
+            rays.push(Ray::new(a - 2.0 * dir, dir));
+            rays.push(Ray::new(0.5 * (a + b) - 2.0 * dir, dir));
+        }
+        assert_matches_brute_force(&triangles, &rays);
+    }
 }
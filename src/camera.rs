@@ -0,0 +1,106 @@
+use core::f32;
+
+use crate::{
+    ray::{Point3, Ray},
+    sampling::concentric_disk,
+    transform::Transform,
+    vec3::Vec3,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub position: Point3,
+    pub look_at: Point3,
+    pub up: Vec3,
+    // Vertical field of view in degrees
+    pub vfov: f32,
+    // Radius of the lens, 0 gives a pinhole camera with everything in focus
+    pub aperture: f32,
+    // Distance from the camera to the plane that is in perfect focus
+    pub focus_distance: f32,
+}
+
+impl Default for Camera {
+    // Matches the original fixed camera, at the origin looking down -Z
+    fn default() -> Camera {
+        Camera {
+            position: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 90.0,
+            aperture: 0.0,
+            focus_distance: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    // Pinhole camera focused on the look at point
+    pub fn new(position: Point3, look_at: Point3, up: Vec3, vfov: f32) -> Camera {
+        Camera {
+            position,
+            look_at,
+            up,
+            vfov,
+            aperture: 0.0,
+            focus_distance: (look_at - position).magnitude(),
+        }
+    }
+
+    // Lays out the image plane on the focal plane for an image of the given size
+    pub fn viewport(&self, width: u32, height: u32) -> Viewport {
+        let viewport_height = 2.0 * f32::tan(self.vfov.to_radians() / 2.0) * self.focus_distance;
+        let viewport_width = viewport_height * (width as f32 / height as f32);
+
+        // Camera basis, w points backwards since the camera looks down -w. A camera looking
+        // straight along `up` has no basis and falls back to looking down -Z
+        let frame = Transform::look_at(self.position, self.look_at, self.up).unwrap_or_default();
+        let u = frame.vector(Vec3::new(1.0, 0.0, 0.0));
+        let v = frame.vector(Vec3::new(0.0, 1.0, 0.0));
+        let w = frame.vector(Vec3::new(0.0, 0.0, 1.0));
+
+        let x_viewport = viewport_width * u;
+        let y_viewport = viewport_height * -v;
+        let x_delta = x_viewport / width as f32;
+        let y_delta = y_viewport / height as f32;
+
+        let pixel_origin =
+            self.position - self.focus_distance * w - x_viewport / 2.0 - y_viewport / 2.0
+                + x_delta / 2.0
+                + y_delta / 2.0;
+
+        Viewport {
+            camera_pos: self.position,
+            pixel_origin,
+            x_delta,
+            y_delta,
+            lens_u: self.aperture * u,
+            lens_v: self.aperture * v,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Viewport {
+    camera_pos: Point3,
+    // Center of the top left pixel
+    pixel_origin: Point3,
+    x_delta: Vec3,
+    y_delta: Vec3,
+    // Lens axes scaled by the aperture radius
+    lens_u: Vec3,
+    lens_v: Vec3,
+}
+
+impl Viewport {
+    // Ray through a point on the image, (x, y) = (0, 0) is the center of the top left pixel.
+    // (lens_x, lens_y) in [0, 1) pick where on the lens the ray starts
+    pub fn get_ray(&self, x: f32, y: f32, lens_x: f32, lens_y: f32) -> Ray {
+        let target = self.pixel_origin + self.x_delta * x + self.y_delta * y;
+
+        let (disk_x, disk_y) = concentric_disk([lens_x, lens_y]);
+        let origin = self.camera_pos + disk_x * self.lens_u + disk_y * self.lens_v;
+
+        Ray::new(origin, target - origin)
+    }
+}
@@ -14,29 +14,175 @@ pub trait Canvas {
     fn width(&self) -> u32;
 }
 
-pub fn to_pixel(color_vec: Vec3) -> Pixel {
-    let intensity = Interval::new(0.0, 0.999);
-    let max_val = f32::max(color_vec.x, f32::max(color_vec.y, color_vec.z));
+// How linear radiance is squeezed into the displayable range
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ToneMapOperator {
+    // Anything over 1 is cut off
+    #[default]
+    Clamp,
+    // Compresses luminance with L / (1 + L), keeping hues
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    // In stops, every +1 doubles the brightness before the operator is applied
+    pub exposure: f32,
+}
 
-    if max_val > 1.0 {
-        let r = (256.0 * intensity.clamp(linear_to_gamma(color_vec.x / max_val))) as u8;
-        let g = (256.0 * intensity.clamp(linear_to_gamma(color_vec.y / max_val))) as u8;
-        let b = (256.0 * intensity.clamp(linear_to_gamma(color_vec.z / max_val))) as u8;
+impl ToneMapping {
+    // Maps linear radiance to linear display values in [0, 1]
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        let color = color * f32::exp2(self.exposure);
 
-        Pixel { r, g, b }
-    } else {
-        let r = (256.0 * intensity.clamp(linear_to_gamma(color_vec.x))) as u8;
-        let g = (256.0 * intensity.clamp(linear_to_gamma(color_vec.y))) as u8;
-        let b = (256.0 * intensity.clamp(linear_to_gamma(color_vec.z))) as u8;
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => color,
+            ToneMapOperator::Reinhard => {
+                let luminance = luminance(color);
+                if luminance > 0.0 {
+                    color / (1.0 + luminance)
+                } else {
+                    color
+                }
+            }
+            ToneMapOperator::Aces => {
+                // Negative values would come out bright, and squaring huge ones overflows to
+                // NaN. Past 1e4 the curve is flat anyway
+                let aces = |x: f32| {
+                    let x = 0.6 * x.clamp(0.0, 1e4);
+                    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+                };
+                Vec3::new(aces(color.x), aces(color.y), aces(color.z))
+            }
+        };
 
-        Pixel { r, g, b }
+        let unit = Interval::new(0.0, 1.0);
+        Vec3::new(
+            unit.clamp(mapped.x),
+            unit.clamp(mapped.y),
+            unit.clamp(mapped.z),
+        )
     }
 }
 
-fn linear_to_gamma(intensity: f32) -> f32 {
-    if intensity > 0.0 {
-        return f32::sqrt(intensity);
+// Relative luminance of a linear Rec. 709 color
+pub fn luminance(color: Vec3) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+pub fn to_pixel(color: Vec3, tone_mapping: &ToneMapping) -> Pixel {
+    let display = tone_mapping.apply(color);
+
+    Pixel {
+        r: encode_srgb(display.x),
+        g: encode_srgb(display.y),
+        b: encode_srgb(display.z),
+    }
+}
+
+// sRGB transfer curve, `intensity` is linear in [0, 1]
+fn encode_srgb(intensity: f32) -> u8 {
+    let encoded = if intensity <= 0.0031308 {
+        12.92 * intensity
+    } else {
+        1.055 * intensity.powf(1.0 / 2.4) - 0.055
+    };
+
+    // NaN from a broken sample ends up as 0 here, rather than poisoning the image
+    (encoded * 255.0 + 0.5) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-5, "{a:?} != {b:?}");
     }
 
-    0.0
+    fn tone_map(operator: ToneMapOperator, exposure: f32, color: Vec3) -> Vec3 {
+        ToneMapping { operator, exposure }.apply(color)
+    }
+
+    #[test]
+    fn operators_map_known_values() {
+        use ToneMapOperator::*;
+
+        let color = Vec3::new(0.5, 2.0, -1.0);
+        assert_close(tone_map(Clamp, 0.0, color), Vec3::new(0.5, 1.0, 0.0));
+
+        // Grey with luminance L becomes L / (1 + L)
+        assert_close(
+            tone_map(Reinhard, 0.0, Vec3::new(1.0, 1.0, 1.0)),
+            Vec3::new(0.5, 0.5, 0.5),
+        );
+        assert_close(
+            tone_map(Reinhard, 0.0, Vec3::new(0.5, 0.5, 0.5)),
+            Vec3::new(1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0),
+        );
+
+        assert_close(
+            tone_map(Aces, 0.0, Vec3::new(0.0, 0.18, 1.0)),
+            Vec3::new(0.0, 0.140120, 0.673290),
+        );
+        // The curve slightly overshoots 1 for very bright values, which is cut off
+        assert_close(
+            tone_map(Aces, 0.0, Vec3::new(100.0, 1e6, 1e30)),
+            Vec3::new(1.0, 1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn exposure_scales_by_stops() {
+        let color = Vec3::new(0.05, 0.1, 0.2);
+        assert_close(
+            tone_map(ToneMapOperator::Clamp, 1.0, color),
+            Vec3::new(0.1, 0.2, 0.4),
+        );
+        assert_close(
+            tone_map(ToneMapOperator::Clamp, -2.0, color),
+            Vec3::new(0.0125, 0.025, 0.05),
+        );
+        // Applied before the operator
+        assert_close(
+            tone_map(ToneMapOperator::Reinhard, 1.0, Vec3::new(0.5, 0.5, 0.5)),
+            Vec3::new(0.5, 0.5, 0.5),
+        );
+    }
+
+    #[test]
+    fn srgb_encodes_known_values() {
+        assert_eq!(encode_srgb(0.0), 0);
+        assert_eq!(encode_srgb(1.0), 255);
+        assert_eq!(encode_srgb(0.18), 118);
+        assert_eq!(encode_srgb(0.5), 188);
+
+        // Linear below the knee, where a plain power curve would give 11
+        assert_eq!(encode_srgb(0.001), 3);
+        // Both pieces of the curve meet at the knee
+        assert_eq!(encode_srgb(0.0031308), 10);
+        assert_eq!(encode_srgb(0.0031309), 10);
+    }
+
+    #[test]
+    fn negative_and_nan_become_black() {
+        assert_eq!(encode_srgb(-0.5), 0);
+        assert_eq!(encode_srgb(f32::NAN), 0);
+
+        for operator in [
+            ToneMapOperator::Clamp,
+            ToneMapOperator::Reinhard,
+            ToneMapOperator::Aces,
+        ] {
+            let tone_mapping = ToneMapping {
+                operator,
+                exposure: 0.0,
+            };
+            let pixel = to_pixel(Vec3::new(-1.0, f32::NAN, -1e30), &tone_mapping);
+            assert_eq!((pixel.r, pixel.g, pixel.b), (0, 0, 0), "{operator:?}");
+        }
+    }
 }
@@ -0,0 +1,192 @@
+// Single part scanline OpenEXR with 32 bit float RGB channels.
+//
+// Blocks of 16 scanlines are ZIP compressed, and stored as they are whenever compressing
+// doesn't make them smaller, which readers recognize by the block size.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path;
+
+use crate::{film::Film, zlib::zlib_compress};
+
+const EXR_MAGIC: u32 = 20000630;
+// Version 2, single part scanline image without long names
+const EXR_VERSION: u32 = 2;
+
+const ZIP_COMPRESSION: u8 = 3;
+const ZIP_SCANLINES: u32 = 16;
+
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+pub fn save_exr(film: &Film, filename: &str) -> Result<(), io::Error> {
+    // Create the directory if needed
+    let path = path::Path::new(filename);
+    if let Some(dir_path) = path.parent() {
+        fs::create_dir_all(dir_path)?;
+    }
+    let mut output = BufWriter::new(File::create(filename)?);
+
+    let header = create_exr_header(film);
+
+    let blocks: Vec<Vec<u8>> = (0..film.height())
+        .step_by(ZIP_SCANLINES as usize)
+        .map(|y| compress_block(&block_data(film, y)))
+        .collect();
+
+    output.write_all(&EXR_MAGIC.to_le_bytes())?;
+    output.write_all(&EXR_VERSION.to_le_bytes())?;
+    output.write_all(&header)?;
+
+    // Table with the file offset of every block, each of which starts with its first scanline
+    // and data size
+    let mut offset = 8 + header.len() as u64 + 8 * blocks.len() as u64;
+    for block in &blocks {
+        output.write_all(&offset.to_le_bytes())?;
+        offset += 8 + block.len() as u64;
+    }
+
+    for (block, y) in blocks
+        .iter()
+        .zip((0..film.height()).step_by(ZIP_SCANLINES as usize))
+    {
+        output.write_all(&(y as i32).to_le_bytes())?;
+        output.write_all(&(block.len() as i32).to_le_bytes())?;
+        output.write_all(block)?;
+    }
+
+    output.into_inner()?.sync_all()
+}
+
+fn create_exr_header(film: &Film) -> Vec<u8> {
+    let mut header = Vec::new();
+
+    // Channels have to be listed in alphabetical order
+    let mut channels = Vec::new();
+    for name in ["B", "G", "R"] {
+        channels.extend(name.as_bytes());
+        channels.push(0);
+        channels.extend(PIXEL_TYPE_FLOAT.to_le_bytes());
+        // Perceptually linear flag and reserved bytes
+        channels.extend([0, 0, 0, 0]);
+        // No subsampling in x or y
+        channels.extend(1i32.to_le_bytes());
+        channels.extend(1i32.to_le_bytes());
+    }
+    channels.push(0);
+
+    let mut window = Vec::new();
+    for value in [0, 0, film.width() as i32 - 1, film.height() as i32 - 1] {
+        window.extend(value.to_le_bytes());
+    }
+
+    let mut screen_window_center = Vec::new();
+    screen_window_center.extend(0f32.to_le_bytes());
+    screen_window_center.extend(0f32.to_le_bytes());
+
+    let attributes: [(&str, &str, &[u8]); 8] = [
+        ("channels", "chlist", &channels),
+        ("compression", "compression", &[ZIP_COMPRESSION]),
+        ("dataWindow", "box2i", &window),
+        ("displayWindow", "box2i", &window),
+        // Increasing y, top scanline first
+        ("lineOrder", "lineOrder", &[0]),
+        ("pixelAspectRatio", "float", &1f32.to_le_bytes()),
+        ("screenWindowCenter", "v2f", &screen_window_center),
+        ("screenWindowWidth", "float", &1f32.to_le_bytes()),
+    ];
+
+    for (name, attribute_type, value) in attributes {
+        header.extend(name.as_bytes());
+        header.push(0);
+        header.extend(attribute_type.as_bytes());
+        header.push(0);
+        header.extend((value.len() as i32).to_le_bytes());
+        header.extend(value);
+    }
+    header.push(0);
+
+    header
+}
+
+// Uncompressed data for the scanlines of the block starting at `first_y`, every scanline holds
+// all of its B values, then G, then R
+fn block_data(film: &Film, first_y: u32) -> Vec<u8> {
+    let last_y = u32::min(first_y + ZIP_SCANLINES, film.height());
+    let mut data = Vec::with_capacity((last_y - first_y) as usize * film.width() as usize * 12);
+
+    for y in first_y..last_y {
+        for channel in [2, 1, 0] {
+            for x in 0..film.width() {
+                let color = film.get_pixel(x, y);
+                let value = [color.x, color.y, color.z][channel];
+                data.extend(value.to_le_bytes());
+            }
+        }
+    }
+
+    data
+}
+
+// OpenEXR's ZIP scheme: the block is prepared for deflate by `interleave_and_predict`
+fn compress_block(data: &[u8]) -> Vec<u8> {
+    let compressed = zlib_compress(&interleave_and_predict(data));
+    if compressed.len() < data.len() {
+        compressed
+    } else {
+        data.to_vec()
+    }
+}
+
+// The bytes are split into even and odd halves and delta encoded, which helps with the slowly
+// changing bytes of neighbouring floats
+fn interleave_and_predict(data: &[u8]) -> Vec<u8> {
+    let mut reordered = Vec::with_capacity(data.len());
+    reordered.extend(data.iter().step_by(2));
+    reordered.extend(data.iter().skip(1).step_by(2));
+
+    let mut previous = reordered.first().copied().unwrap_or(0);
+    for byte in reordered.iter_mut().skip(1) {
+        let current = *byte;
+        *byte = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+
+    reordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What a reader does after inflating: undoes the deltas, then puts the halves back together
+    fn reverse(predicted: &[u8]) -> Vec<u8> {
+        let mut reordered = predicted.to_vec();
+        for i in 1..reordered.len() {
+            reordered[i] = reordered[i]
+                .wrapping_add(reordered[i - 1])
+                .wrapping_sub(128);
+        }
+
+        let (even, odd) = reordered.split_at(reordered.len().div_ceil(2));
+        let mut data = Vec::with_capacity(predicted.len());
+        for (i, &byte) in even.iter().enumerate() {
+            data.push(byte);
+            if let Some(&byte) = odd.get(i) {
+                data.push(byte);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn predictor_and_interleave_reverse() {
+        // A block's worth of floats in the layout `block_data` writes, plus an odd length
+        let floats: Vec<u8> = (0..ZIP_SCANLINES * 5 * 3)
+            .flat_map(|i| (i as f32 * 0.37).sin().to_le_bytes())
+            .collect();
+
+        for data in [&floats[..], &floats[..floats.len() - 1], &[], &[7]] {
+            assert_eq!(reverse(&interleave_and_predict(data)), data);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{
+    canvas::{Canvas, ToneMapping, luminance, to_pixel},
+    vec3::Vec3,
+};
+
+// Running statistics of the samples taken for one pixel, updated one sample at a time with
+// Welford's algorithm so no samples have to be kept around
+#[derive(Clone, Copy, Default)]
+pub struct PixelStats {
+    pub count: u32,
+    mean: Vec3,
+    luminance_mean: f32,
+    // Sum of squared differences of the luminance from its mean
+    luminance_m2: f32,
+}
+
+impl PixelStats {
+    pub fn add(&mut self, color: Vec3) {
+        self.count += 1;
+        let count = self.count as f32;
+        self.mean = self.mean + (color - self.mean) / count;
+
+        let luminance = luminance(color);
+        let delta = luminance - self.luminance_mean;
+        self.luminance_mean += delta / count;
+        self.luminance_m2 += delta * (luminance - self.luminance_mean);
+    }
+
+    pub fn mean(&self) -> Vec3 {
+        self.mean
+    }
+
+    // Estimated standard deviation of the mean luminance from the true pixel value, over the
+    // square root of the pixel's brightness since the same error is harder to see in bright
+    // pixels. Pixels too dark to see count as having luminance 0.001
+    pub fn noise(&self) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY;
+        }
+
+        let count = self.count as f32;
+        let variance = self.luminance_m2 / (count - 1.0);
+        f32::sqrt(variance / count) / f32::sqrt(f32::max(self.luminance_mean, 0.001))
+    }
+}
+
+// Linear, unclamped radiance for every pixel of the image
+pub struct Film {
+    width: u32,
+    height: u32,
+    pixels: Vec<PixelStats>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        Film {
+            width,
+            height,
+            pixels: vec![PixelStats::default(); width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel_stats(&self, x: u32, y: u32) -> PixelStats {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set_pixel_stats(&mut self, x: u32, y: u32, stats: PixelStats) {
+        self.pixels[(y * self.width + x) as usize] = stats;
+    }
+
+    // Mean of the samples taken for the pixel
+    pub fn get_pixel(&self, x: u32, y: u32) -> Vec3 {
+        self.pixels[(y * self.width + x) as usize].mean()
+    }
+
+    // Grey image of how many samples each pixel took, as a fraction of the most any pixel took
+    pub fn sample_count_map(&self) -> Film {
+        let most = self
+            .pixels
+            .iter()
+            .map(|stats| stats.count)
+            .max()
+            .unwrap_or(0);
+        let mut map = Film::new(self.width, self.height);
+
+        for (stats, map_stats) in self.pixels.iter().zip(&mut map.pixels) {
+            let fraction = stats.count as f32 / u32::max(most, 1) as f32;
+            map_stats.add(Vec3::new(fraction, fraction, fraction));
+        }
+
+        map
+    }
+
+    // Tone maps the film down to 8 bit sRGB pixels, cropping to whichever is smaller
+    pub fn develop(&self, canvas: &mut impl Canvas, tone_mapping: &ToneMapping) {
+        for y in 0..u32::min(self.height, canvas.height()) {
+            for x in 0..u32::min(self.width, canvas.width()) {
+                canvas.set_pixel(x, y, to_pixel(self.get_pixel(x, y), tone_mapping));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_stats_match_two_pass_statistics() {
+        let samples = [0.5, 2.0, 0.25, 4.0, 1.0, 0.0, 3.5];
+        let mut stats = PixelStats::default();
+        for value in samples {
+            stats.add(Vec3::new(value, value, value));
+        }
+
+        let count = samples.len() as f32;
+        let mean = samples.iter().sum::<f32>() / count;
+        let variance = samples.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / (count - 1.0);
+
+        assert!((stats.mean().x - mean).abs() < 1e-5);
+        let expected_noise = f32::sqrt(variance / count) / f32::sqrt(mean);
+        assert!((stats.noise() - expected_noise).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,224 @@
+// Writers for the simple floating point image formats, both keep the film's linear radiance
+// without tone mapping or clamping.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path;
+
+use crate::{film::Film, vec3::Vec3};
+
+// Portable float map, three little endian f32s per pixel with the bottom row first
+pub fn save_pfm(film: &Film, filename: &str) -> Result<(), io::Error> {
+    let mut output = create_file(filename)?;
+
+    // A negative scale marks the data as little endian
+    write!(output, "PF\n{} {}\n-1.0\n", film.width(), film.height())?;
+
+    for y in (0..film.height()).rev() {
+        for x in 0..film.width() {
+            let color = film.get_pixel(x, y);
+            for channel in [color.x, color.y, color.z] {
+                output.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+
+    output.into_inner()?.sync_all()
+}
+
+// Radiance RGBE, run length encoded the way Radiance itself writes it whenever the width allows
+pub fn save_radiance_hdr(film: &Film, filename: &str) -> Result<(), io::Error> {
+    let mut output = create_file(filename)?;
+
+    write!(
+        output,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        film.height(),
+        film.width()
+    )?;
+
+    let width = film.width() as usize;
+    let mut scanline = vec![[0u8; 4]; width];
+
+    for y in 0..film.height() {
+        for (x, rgbe) in scanline.iter_mut().enumerate() {
+            *rgbe = to_rgbe(film.get_pixel(x as u32, y));
+        }
+
+        // Run length encoding is only defined for widths from 8 to 32767
+        if !(8..32768).contains(&width) {
+            output.write_all(scanline.as_flattened())?;
+            continue;
+        }
+
+        output.write_all(&[2, 2, (width >> 8) as u8, width as u8])?;
+        for channel in 0..4 {
+            let bytes: Vec<u8> = scanline.iter().map(|rgbe| rgbe[channel]).collect();
+            write_rle_channel(&mut output, &bytes)?;
+        }
+    }
+
+    output.into_inner()?.sync_all()
+}
+
+fn create_file(filename: &str) -> Result<BufWriter<File>, io::Error> {
+    // Create the directory if needed
+    let path = path::Path::new(filename);
+    if let Some(dir_path) = path.parent() {
+        fs::create_dir_all(dir_path)?;
+    }
+    Ok(BufWriter::new(File::create(filename)?))
+}
+
+// Shared exponent encoding, the mantissas keep 8 bits relative to the brightest channel
+fn to_rgbe(color: Vec3) -> [u8; 4] {
+    // Negative and non finite values can't be represented, 1e38 keeps the exponent in range
+    let channels =
+        [color.x, color.y, color.z].map(|c| if c.is_nan() { 0.0 } else { c.clamp(0.0, 1e38) });
+    let max = f32::max(channels[0], f32::max(channels[1], channels[2]));
+
+    if max < 1e-32 {
+        return [0; 4];
+    }
+
+    // max = m * 2^exponent with m in [0.5, 1)
+    let exponent = ((max.to_bits() >> 23) & 0xff) as i32 - 126;
+    let scale = f32::powi(2.0, 8 - exponent);
+
+    let [r, g, b] = channels.map(|c| (c * scale) as u8);
+    [r, g, b, (exponent + 128) as u8]
+}
+
+// Runs of at least 4 equal bytes are stored as (128 + length, byte), everything in between
+// as (length, bytes...), lengths are at most 127 and 128 respectively
+fn write_rle_channel(output: &mut impl Write, bytes: &[u8]) -> Result<(), io::Error> {
+    const MIN_RUN: usize = 4;
+
+    let run_length = |start: usize| {
+        bytes[start..]
+            .iter()
+            .take(127)
+            .take_while(|&&byte| byte == bytes[start])
+            .count()
+    };
+
+    let mut pos = 0;
+    while pos < bytes.len() {
+        // Collect literals up to the next run worth encoding
+        let mut literal_end = pos;
+        while literal_end < bytes.len()
+            && literal_end - pos < 128
+            && run_length(literal_end) < MIN_RUN
+        {
+            literal_end += 1;
+        }
+
+        if literal_end > pos {
+            output.write_all(&[(literal_end - pos) as u8])?;
+            output.write_all(&bytes[pos..literal_end])?;
+            pos = literal_end;
+            continue;
+        }
+
+        let length = run_length(pos);
+        output.write_all(&[128 + length as u8, bytes[pos]])?;
+        pos += length;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Radiance's decoding, which puts each mantissa in the middle of its step
+    fn from_rgbe([r, g, b, e]: [u8; 4]) -> Vec3 {
+        if e == 0 {
+            return Vec3::default();
+        }
+        let scale = f32::powi(2.0, e as i32 - (128 + 8));
+        Vec3::new(
+            (r as f32 + 0.5) * scale,
+            (g as f32 + 0.5) * scale,
+            (b as f32 + 0.5) * scale,
+        )
+    }
+
+    #[test]
+    fn rgbe_round_trips_within_a_mantissa_step() {
+        let colors = [
+            Vec3::new(0.5, 0.25, 1.0),
+            Vec3::new(1234.5, 0.001, 77.0),
+            Vec3::new(3e-30, 1e-30, 0.0),
+            Vec3::new(1e30, 5e29, 1e29),
+            Vec3::new(0.0, 0.0, 2.0e-20),
+        ];
+
+        for color in colors {
+            let decoded = from_rgbe(to_rgbe(color));
+            let max = f32::max(color.x, f32::max(color.y, color.z));
+            // Every channel shares the brightest one's exponent, so errors are relative to it
+            for (a, b) in [
+                (color.x, decoded.x),
+                (color.y, decoded.y),
+                (color.z, decoded.z),
+            ] {
+                assert!(
+                    (a - b).abs() <= max / 128.0,
+                    "{color:?} decoded as {decoded:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rgbe_stores_black_and_tiny_values_as_zero() {
+        assert_eq!(to_rgbe(Vec3::default()), [0; 4]);
+        assert_eq!(to_rgbe(Vec3::new(1e-35, 0.0, 1e-33)), [0; 4]);
+        assert_eq!(to_rgbe(Vec3::new(-1.0, f32::NAN, 0.0)), [0; 4]);
+    }
+
+    fn run_length_decode(mut encoded: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while let [count, rest @ ..] = encoded {
+            if *count > 128 {
+                bytes.extend(std::iter::repeat_n(rest[0], *count as usize - 128));
+                encoded = &rest[1..];
+            } else {
+                assert!(*count > 0, "zero length literal");
+                bytes.extend(&rest[..*count as usize]);
+                encoded = &rest[*count as usize..];
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn run_length_encoding_round_trips() {
+        let literals: Vec<u8> = (0..300).map(|i| (i * 7 % 251) as u8).collect();
+        let scanlines = [
+            // Runs longer than a single run can hold
+            vec![9; 300],
+            // Literals longer than a single literal can hold
+            literals.clone(),
+            // Runs at the very end, one long enough to encode and one too short
+            [&literals[..10], &[5; 4]].concat(),
+            [&literals[..10], &[5; 3]].concat(),
+            [&[5; 130], &literals[..3], &[6; 127]].concat(),
+            vec![1],
+            Vec::new(),
+        ];
+
+        for scanline in scanlines {
+            let mut encoded = Vec::new();
+            write_rle_channel(&mut encoded, &scanline).unwrap();
+            assert_eq!(run_length_decode(&encoded), scanline);
+        }
+
+        // 300 equal bytes take three runs
+        let mut encoded = Vec::new();
+        write_rle_channel(&mut encoded, &[9; 300]).unwrap();
+        assert_eq!(encoded, [255, 9, 255, 9, 128 + 46, 9]);
+    }
+}
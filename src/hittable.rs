@@ -1,17 +1,60 @@
 use crate::{
+    bbox::Bbox,
+    light::LightShape,
     material::Material,
     ray::{Interval, Point3, Ray},
     vec3::Vec3,
 };
 
 pub trait Hittable {
-    fn hit(&self, ray: Ray, interval: Interval, hit_info_out: &mut HitInfo) -> bool;
+    fn hit<'a>(&'a self, ray: Ray, interval: Interval, hit_info_out: &mut HitInfo<'a>) -> bool;
+
+    // Box around everything `hit` can find
+    fn bounds(&self) -> Bbox;
+
+    // Emissive surfaces with their emission, so they can be sampled directly
+    fn lights(&self) -> Vec<(LightShape, Vec3)> {
+        Vec::new()
+    }
 }
 
-#[derive(Default)]
-pub struct HitInfo {
+pub struct HitInfo<'a> {
     pub t: f32,
+    // True normal of the surface, always faces against the incoming ray
     pub normal: Vec3,
+    // Normal the materials shade with, on the same side as `normal`. Smooth shading interpolates
+    // it from vertex normals, while `normal` keeps deciding which side of the surface rays are on
+    pub shading_normal: Vec3,
+    // Weights of the second and third corner of the triangle that was hit, the first corner gets
+    // the rest. Zero for other shapes
+    pub barycentric: [f32; 2],
+    // Whether the ray hit the side the surface's normal points out of, i.e. is entering it
+    pub front_face: bool,
     pub point: Point3,
-    pub material: Material,
+    // Borrowed from whatever was hit, so looking it up per hit costs nothing
+    pub material: &'a Material,
+}
+
+// Stands in until something is hit
+static NO_MATERIAL: Material = Material {
+    bsdf: None,
+    emission: Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    },
+};
+
+impl Default for HitInfo<'_> {
+    fn default() -> Self {
+        HitInfo {
+            t: 0.0,
+            normal: Vec3::default(),
+            shading_normal: Vec3::default(),
+            barycentric: [0.0, 0.0],
+            front_face: false,
+            point: Point3::default(),
+            material: &NO_MATERIAL,
+        }
+    }
 }
@@ -0,0 +1,63 @@
+use std::{io, path::Path};
+
+use crate::{
+    bmp::BmpCanvas,
+    canvas::ToneMapping,
+    exr::save_exr,
+    film::Film,
+    hdr::{save_pfm, save_radiance_hdr},
+    png::save_png,
+};
+
+#[derive(Clone, Copy)]
+pub enum ImageFormat {
+    Bmp,
+    Png,
+    // The floating point formats store the linear radiance, without tone mapping
+    Pfm,
+    RadianceHdr,
+    Exr,
+}
+
+impl ImageFormat {
+    // The format is chosen by the file extension, in any case
+    pub fn from_filename(filename: &str) -> Option<ImageFormat> {
+        let extension = Path::new(filename).extension()?.to_str()?;
+
+        match extension.to_ascii_lowercase().as_str() {
+            "bmp" => Some(ImageFormat::Bmp),
+            "png" => Some(ImageFormat::Png),
+            "pfm" => Some(ImageFormat::Pfm),
+            "hdr" => Some(ImageFormat::RadianceHdr),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+}
+
+// Saves the film in the format matching the extension of `filename`, 8 bit formats are
+// developed with `tone_mapping` first
+pub fn save_image(film: &Film, tone_mapping: &ToneMapping, filename: &str) -> io::Result<()> {
+    let Some(format) = ImageFormat::from_filename(filename) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{filename}: unsupported image format, expected .bmp, .png, .pfm, .hdr or .exr"
+            ),
+        ));
+    };
+
+    let develop = || {
+        let mut canvas = BmpCanvas::new(film.width(), film.height());
+        film.develop(&mut canvas, tone_mapping);
+        canvas
+    };
+
+    match format {
+        ImageFormat::Bmp => develop().save_image(filename),
+        ImageFormat::Png => save_png(&develop(), filename),
+        ImageFormat::Pfm => save_pfm(film, filename),
+        ImageFormat::RadianceHdr => save_radiance_hdr(film, filename),
+        ImageFormat::Exr => save_exr(film, filename),
+    }
+}
@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use crate::{
+    bbox::Bbox,
+    hittable::{HitInfo, Hittable},
+    light::LightShape,
+    material::Material,
+    ray::{Interval, Point3, Ray},
+    transform::Transform,
+    triangle_mesh::TriangleMesh,
+    vec3::Vec3,
+};
+
+// A placed copy of a mesh. Every instance of a mesh shares its triangles and bvh, so a thousand
+// copies only cost a transform each
+pub struct Instance {
+    mesh: Arc<TriangleMesh>,
+    to_world: Transform,
+    to_object: Transform,
+    // Replaces the mesh's own material
+    material: Option<Material>,
+}
+
+impl Instance {
+    pub fn new(
+        mesh: Arc<TriangleMesh>,
+        to_world: Transform,
+        material: Option<Material>,
+    ) -> Instance {
+        // Build the mesh's bvh up front instead of on the first ray that reaches it
+        mesh.bvh();
+
+        Instance {
+            mesh,
+            to_world,
+            to_object: to_world.inverse(),
+            material,
+        }
+    }
+
+    fn material(&self) -> &Material {
+        self.material.as_ref().unwrap_or(self.mesh.material())
+    }
+}
+
+impl Hittable for Instance {
+    fn hit<'a>(&'a self, ray: Ray, interval: Interval, hit_info_out: &mut HitInfo<'a>) -> bool {
+        // The direction isn't normalized after transforming, so distances along the ray stay the
+        // same in both spaces
+        let object_ray = Ray::new(
+            self.to_object.point(ray.origin()),
+            self.to_object.vector(ray.dir()),
+        );
+
+        if !self.mesh.hit(object_ray, interval, hit_info_out) {
+            return false;
+        }
+
+        let normal = self.to_world.normal(hit_info_out.normal);
+        hit_info_out.normal = normal / normal.magnitude();
+        let shading_normal = self.to_world.normal(hit_info_out.shading_normal);
+        hit_info_out.shading_normal = shading_normal / shading_normal.magnitude();
+        hit_info_out.point = ray.at(hit_info_out.t);
+        hit_info_out.material = self.material();
+
+        true
+    }
+
+    fn bounds(&self) -> Bbox {
+        let bounds = self.mesh.bounds();
+        let mut min = Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut max = -min;
+
+        // The box around the mesh's box, transformed corner by corner
+        for corner in 0..8 {
+            let [x, y, z] =
+                [0, 1, 2].map(|axis| bounds.axis_interval(axis).get_val((corner >> axis) & 1));
+            let p = self.to_world.point(Point3::new(x, y, z));
+
+            min = Vec3::new(
+                f32::min(min.x, p.x),
+                f32::min(min.y, p.y),
+                f32::min(min.z, p.z),
+            );
+            max = Vec3::new(
+                f32::max(max.x, p.x),
+                f32::max(max.y, p.y),
+                f32::max(max.z, p.z),
+            );
+        }
+
+        Bbox::new(
+            Interval::new(min.x, max.x),
+            Interval::new(min.y, max.y),
+            Interval::new(min.z, max.z),
+        )
+    }
+
+    fn lights(&self) -> Vec<(LightShape, Vec3)> {
+        let emission = self.material().emission;
+        if emission.magnitude() <= 0.0 {
+            return Vec::new();
+        }
+
+        self.mesh
+            .triangles()
+            .map(|triangle| {
+                let triangle = triangle.map(|p| self.to_world.point(p));
+                (LightShape::Triangle(triangle), emission)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::rng::Pcg32;
+
+    fn assert_close(a: Vec3, b: Vec3, tolerance: f32) {
+        assert!((a - b).magnitude() < tolerance, "{a:?} != {b:?}");
+    }
+
+    // Gently bumped 3 by 3 grid with smoothed vertex normals, so the shading normals differ from
+    // the faces
+    fn bumpy_grid(material: Material) -> TriangleMesh {
+        let mut mesh = TriangleMesh::new(material);
+        for y in 0..4 {
+            for x in 0..4 {
+                let height = 0.15 * ((x * 7 + y * 5) % 3) as f32;
+                mesh.add_vertex(Point3::new(x as f32, y as f32, height));
+            }
+        }
+        for y in 0..3 {
+            for x in 0..3 {
+                let corner = y * 4 + x;
+                mesh.add_triangle(corner, corner + 1, corner + 5);
+                mesh.add_triangle(corner, corner + 5, corner + 4);
+            }
+        }
+        mesh.smooth_normals(180.0);
+        mesh
+    }
+
+    fn grey() -> Material {
+        Material::lambertian(Vec3::new(0.5, 0.5, 0.5))
+    }
+
+    // Non-uniform scale, which a normal can't just be rotated through
+    fn to_world() -> Transform {
+        Transform::scale(Vec3::new(3.0, 0.5, 1.0))
+            .then(Transform::rotate(Vec3::new(1.0, 1.0, 0.0), 30.0))
+            .then(Transform::translate(Vec3::new(1.0, -2.0, 3.0)))
+    }
+
+    fn hit<'a>(object: &'a dyn Hittable, ray: Ray) -> Option<HitInfo<'a>> {
+        let mut hit_info = HitInfo {
+            t: f32::INFINITY,
+            ..Default::default()
+        };
+        object
+            .hit(ray, Interval::new(0.001, f32::INFINITY), &mut hit_info)
+            .then_some(hit_info)
+    }
+
+    #[test]
+    fn hits_match_the_baked_mesh() {
+        let metal = Material::metal(Vec3::new(0.9, 0.8, 0.7), 0.1);
+        let instance = Instance::new(Arc::new(bumpy_grid(grey())), to_world(), Some(metal));
+        let mut baked = bumpy_grid(grey());
+        baked.transform(&to_world());
+        let triangles: Vec<[Point3; 3]> = baked.triangles().collect();
+
+        let mut rng = Pcg32::new(11, 0);
+        for _ in 0..2000 {
+            // From somewhere around the mesh towards a point well inside one of its triangles
+            let [a, b, c] = triangles[rng.random_range(0..triangles.len())];
+            let (u, v) = (rng.random_range(0.1..0.8), rng.random_range(0.1..0.8));
+            let (u, v) = if u + v > 0.9 {
+                (0.9 - v, 0.9 - u)
+            } else {
+                (u, v)
+            };
+            let target = a + u * (b - a) + v * (c - a);
+            let offset = Vec3::new(
+                rng.random_range(-5.0..5.0),
+                rng.random_range(-5.0..5.0),
+                rng.random_range(-5.0..5.0),
+            );
+            let ray = Ray::new(target + offset, -rng.random_range(0.5..2.0) * offset);
+
+            let expected = hit(&baked, ray).expect("baked mesh missed");
+            let actual = hit(&instance, ray).expect("instance missed");
+            assert!((actual.t - expected.t).abs() < 1e-3 * expected.t);
+            assert_close(
+                actual.point,
+                expected.point,
+                1e-3 * expected.point.magnitude(),
+            );
+            assert_close(actual.normal, expected.normal, 1e-3);
+            // Interpolating before or after the transform only agrees exactly at the corners,
+            // but the corners of a smooth surface hardly differ
+            assert_close(actual.shading_normal, expected.shading_normal, 2e-2);
+            assert_eq!(actual.front_face, expected.front_face);
+            assert!(std::ptr::eq(
+                actual.material,
+                instance.material.as_ref().unwrap()
+            ));
+        }
+
+        // Past the side of the mesh
+        let beside = Ray::new(Point3::new(20.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(hit(&instance, beside).is_none());
+    }
+
+    #[test]
+    fn uses_the_mesh_material_without_an_override() {
+        let instance = Instance::new(Arc::new(bumpy_grid(grey())), to_world(), None);
+        let center = to_world().point(Point3::new(1.5, 1.5, 0.1));
+        let ray = Ray::new(
+            center + Vec3::new(0.0, 0.0, 10.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+
+        let hit_info = hit(&instance, ray).unwrap();
+        assert!(std::ptr::eq(hit_info.material, instance.mesh.material()));
+    }
+
+    #[test]
+    fn bounds_contain_the_baked_mesh() {
+        let instance = Instance::new(Arc::new(bumpy_grid(grey())), to_world(), None);
+        let mut baked = bumpy_grid(grey());
+        baked.transform(&to_world());
+
+        let bounds = instance.bounds();
+        for p in baked.triangles().flatten() {
+            for (axis, value) in [p.x, p.y, p.z].into_iter().enumerate() {
+                assert!(bounds.axis_interval(axis).contains(value));
+            }
+        }
+
+        // Without a rotation the box is as tight as the baked one, up to the padding
+        let to_world = Transform::scale(Vec3::new(3.0, 0.5, 1.0))
+            .then(Transform::translate(Vec3::new(1.0, -2.0, 3.0)));
+        let instance = Instance::new(Arc::new(bumpy_grid(grey())), to_world, None);
+        let mut baked = bumpy_grid(grey());
+        baked.transform(&to_world);
+
+        let (instance_bounds, baked_bounds) = (instance.bounds(), baked.bounds());
+        for axis in 0..3 {
+            for end in 0..2 {
+                let instance_end = instance_bounds.axis_interval(axis).get_val(end);
+                let baked_end = baked_bounds.axis_interval(axis).get_val(end);
+                assert!((instance_end - baked_end).abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn lights_are_the_baked_triangles() {
+        let plain = Instance::new(Arc::new(bumpy_grid(grey())), to_world(), None);
+        assert!(plain.lights().is_empty());
+
+        let emission = Vec3::new(1.0, 2.0, 3.0);
+        let instance = Instance::new(
+            Arc::new(bumpy_grid(grey())),
+            to_world(),
+            Some(Material::emissive(emission)),
+        );
+        let mut baked = bumpy_grid(grey());
+        baked.transform(&to_world());
+
+        let lights = instance.lights();
+        assert_eq!(lights.len(), baked.triangles().count());
+        for ((shape, light_emission), expected) in lights.iter().zip(baked.triangles()) {
+            let LightShape::Triangle(triangle) = shape else {
+                panic!("not a triangle");
+            };
+            for (&p, q) in triangle.iter().zip(expected) {
+                assert_close(p, q, 1e-4);
+            }
+            assert_close(*light_emission, emission, 1e-6);
+        }
+    }
+}
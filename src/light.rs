@@ -0,0 +1,245 @@
+// Emissive shapes gathered from the scene so they can be sampled directly.
+//
+// Shapes are picked in proportion to their power (area times luminance) and points on them
+// uniformly by area, so the density of a sampled point only depends on its emission.
+
+use core::f32;
+
+use crate::{canvas::luminance, ray::Point3, sampling::uniform_sphere, vec3::Vec3};
+
+pub enum LightShape {
+    Triangle([Point3; 3]),
+    Sphere { center: Point3, radius: f32 },
+}
+
+impl LightShape {
+    fn area(&self) -> f32 {
+        match *self {
+            LightShape::Triangle([a, b, c]) => 0.5 * (b - a).cross(c - a).magnitude(),
+            LightShape::Sphere { radius, .. } => 4.0 * f32::consts::PI * radius * radius,
+        }
+    }
+
+    // Point uniformly distributed over the surface, and the unit normal there
+    fn sample(&self, u1: f32, u2: f32) -> (Point3, Vec3) {
+        match *self {
+            LightShape::Triangle([a, b, c]) => {
+                // Uniform barycentrics, folding the unit square onto the triangle
+                let (mut b1, mut b2) = (u1, u2);
+                if b1 + b2 > 1.0 {
+                    (b1, b2) = (1.0 - b1, 1.0 - b2);
+                }
+
+                let normal = (b - a).cross(c - a);
+                (a + b1 * (b - a) + b2 * (c - a), normal / normal.magnitude())
+            }
+            // Points on the far side are hidden by the sphere itself, so shadow rays reject them
+            LightShape::Sphere { center, radius } => {
+                let normal = uniform_sphere([u1, u2]);
+                (center + radius * normal, normal)
+            }
+        }
+    }
+}
+
+struct Light {
+    shape: LightShape,
+    emission: Vec3,
+}
+
+pub struct LightSample {
+    pub point: Point3,
+    // Unit normal of the light, emitters are two sided so its orientation doesn't matter
+    pub normal: Vec3,
+    pub emission: Vec3,
+    // Probability density of having picked `point`, per unit area
+    pub pdf_area: f32,
+}
+
+#[derive(Default)]
+pub struct LightList {
+    lights: Vec<Light>,
+    // Running sum of the light powers, the last entry is the total
+    power_cdf: Vec<f32>,
+}
+
+impl LightList {
+    pub fn new(shapes: impl Iterator<Item = (LightShape, Vec3)>) -> LightList {
+        let mut lights = LightList::default();
+        let mut total_power = 0.0;
+
+        for (shape, emission) in shapes {
+            let power = shape.area() * luminance(emission);
+
+            if power <= 0.0 || !power.is_finite() {
+                continue;
+            }
+
+            total_power += power;
+            lights.power_cdf.push(total_power);
+            lights.lights.push(Light { shape, emission });
+        }
+
+        lights
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    fn total_power(&self) -> f32 {
+        self.power_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    // Picks a light with `u_select` and a point on it with `u1`, `u2`, all uniform in [0, 1)
+    pub fn sample(&self, u_select: f32, u1: f32, u2: f32) -> Option<LightSample> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let target = u_select * self.total_power();
+        let idx = usize::min(
+            self.power_cdf.partition_point(|&power| power <= target),
+            self.lights.len() - 1,
+        );
+        let light = &self.lights[idx];
+        let (point, normal) = light.shape.sample(u1, u2);
+
+        Some(LightSample {
+            point,
+            normal,
+            emission: light.emission,
+            pdf_area: self.pdf_area(light.emission),
+        })
+    }
+
+    // Density per unit area of `sample` returning a point on a light with this emission
+    pub fn pdf_area(&self, emission: Vec3) -> f32 {
+        luminance(emission) / self.total_power()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::rng::Pcg32;
+
+    // Lights told apart by the red channel of their emission
+    fn lights() -> LightList {
+        let emission = |value: f32, id: f32| Vec3::new(id, value, value);
+        LightList::new(
+            [
+                (
+                    LightShape::Triangle([
+                        Point3::new(0.0, 0.0, 0.0),
+                        Point3::new(1.0, 0.0, 0.0),
+                        Point3::new(0.0, 1.0, 0.0),
+                    ]),
+                    emission(4.0, 0.0),
+                ),
+                (
+                    LightShape::Triangle([
+                        Point3::new(0.0, 0.0, 2.0),
+                        Point3::new(3.0, 0.0, 2.0),
+                        Point3::new(0.0, 0.0, 5.0),
+                    ]),
+                    emission(0.5, 1.0),
+                ),
+                // Not emitting, never picked
+                (
+                    LightShape::Triangle([
+                        Point3::new(0.0, 0.0, 0.0),
+                        Point3::new(9.0, 0.0, 0.0),
+                        Point3::new(0.0, 9.0, 0.0),
+                    ]),
+                    Vec3::default(),
+                ),
+                (
+                    LightShape::Sphere {
+                        center: Point3::new(0.0, 5.0, 0.0),
+                        radius: 0.3,
+                    },
+                    emission(10.0, 2.0),
+                ),
+            ]
+            .into_iter(),
+        )
+    }
+
+    #[test]
+    fn picks_lights_in_proportion_to_their_power() {
+        let lights = lights();
+        let powers: Vec<f32> = lights
+            .lights
+            .iter()
+            .map(|light| light.shape.area() * luminance(light.emission))
+            .collect();
+        assert_eq!(powers.len(), 3);
+        let total: f32 = powers.iter().sum();
+
+        const N: usize = 100_000;
+        let mut counts = [0; 3];
+        for i in 0..N {
+            let sample = lights
+                .sample((i as f32 + 0.5) / N as f32, 0.5, 0.5)
+                .unwrap();
+            counts[sample.emission.x as usize] += 1;
+        }
+
+        for (count, power) in counts.into_iter().zip(powers) {
+            assert!(
+                (count as f32 / N as f32 - power / total).abs() < 1e-4,
+                "{counts:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn pdf_area_matches_the_sampled_points() {
+        let lights = lights();
+
+        // A density over all the light surfaces integrates to 1
+        let integral: f32 = lights
+            .lights
+            .iter()
+            .map(|light| light.shape.area() * lights.pdf_area(light.emission))
+            .sum();
+        assert!((integral - 1.0).abs() < 1e-5);
+
+        // The fraction of points landing in a region is the density times its area. Checked for
+        // the part of the first triangle with x + y < 0.5, area 1/8, and the top half of the
+        // sphere
+        let mut rng = Pcg32::new(5, 0);
+        const N: usize = 200_000;
+        let (mut in_triangle, mut in_sphere) = (0, 0);
+        for _ in 0..N {
+            let sample = lights
+                .sample(rng.random(), rng.random(), rng.random())
+                .unwrap();
+            assert_eq!(sample.pdf_area, lights.pdf_area(sample.emission));
+
+            let p = sample.point;
+            match sample.emission.x as usize {
+                0 if p.x + p.y < 0.5 => in_triangle += 1,
+                2 if p.y > 5.0 => in_sphere += 1,
+                _ => {}
+            }
+        }
+
+        let expected_triangle = lights.pdf_area(Vec3::new(0.0, 4.0, 4.0)) * 0.125;
+        let sphere_area = 4.0 * f32::consts::PI * 0.3 * 0.3;
+        let expected_sphere = lights.pdf_area(Vec3::new(2.0, 10.0, 10.0)) * sphere_area / 2.0;
+        for (count, expected) in [
+            (in_triangle, expected_triangle),
+            (in_sphere, expected_sphere),
+        ] {
+            let fraction = count as f32 / N as f32;
+            assert!(
+                (fraction - expected).abs() < 0.05 * expected,
+                "{fraction} != {expected}"
+            );
+        }
+    }
+}
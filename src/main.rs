@@ -1,36 +1,112 @@
 #![feature(portable_simd)]
 
-use std::{io, time::SystemTime};
-
-use bmp::BmpCanvas;
-use material::{Material, MaterialType};
-use raytracer::RayTracer;
+use std::{env, io, process, time::SystemTime};
+
+use camera::Camera;
+use canvas::ToneMapping;
+use film::Film;
+use image_output::save_image;
+use material::Material;
+use raytracer::{RayTracer, RenderSettings};
+use scene_file::{SceneDescription, load_scene};
 use triangle_mesh::{Scene, TriangleMesh};
 use vec3::Vec3;
 
 mod bbox;
 mod bmp;
+mod bsdf;
 mod bvh;
+mod camera;
 mod canvas;
+mod exr;
+mod film;
+mod hdr;
 mod hittable;
+mod image_output;
+mod instance;
+mod light;
 mod material;
+mod matrix;
+mod obj;
+mod png;
+mod quad;
+mod quat;
 mod ray;
 mod raytracer;
+mod rng;
+mod sampler;
+mod sampling;
+mod scene_file;
+mod sphere;
+mod transform;
 mod triangle_mesh;
 mod vec3;
+mod zlib;
 
 fn main() -> Result<(), io::Error> {
+    // Render the scene file given on the command line, or the built in scene without one
+    let description = match env::args().nth(1) {
+        Some(filename) => match load_scene(&filename) {
+            Ok(description) => description,
+            Err(err) => {
+                eprintln!("failed to load scene: {err}");
+                process::exit(1);
+            }
+        },
+        None => default_scene(),
+    };
+
+    let mut film = Film::new(description.width, description.height);
+
+    let raytracer = RayTracer::new(description.camera);
+
+    let start_time = SystemTime::now();
+
+    // Snapshots overwrite the output, so the image so far can be looked at during long renders
+    let snapshot = |film: &Film, samples: u32| {
+        let saved = save_image(
+            film,
+            &description.settings.tone_mapping,
+            &description.output,
+        );
+        match saved {
+            Ok(()) => println!("saved snapshot at {samples} samples per pixel"),
+            Err(err) => eprintln!("failed to save snapshot: {err}"),
+        }
+    };
+
+    raytracer.draw(&mut film, &description.scene, &description.settings, snapshot);
+
+    println!(
+        "rendered in {} ms",
+        start_time.elapsed().unwrap().as_millis()
+    );
+
+    save_image(
+        &film,
+        &description.settings.tone_mapping,
+        &description.output,
+    )?;
+
+    if let Some(sample_map) = &description.sample_map {
+        save_image(
+            &film.sample_count_map(),
+            &ToneMapping::default(),
+            sample_map,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn default_scene() -> SceneDescription {
     let width: u32 = 2560;
     let aspect_ratio = 16.0 / 9.0;
     let height: u32 = (width as f32 / aspect_ratio) as u32;
 
-    let mut bmp_canvas = BmpCanvas::new(width, height);
     let mut scene = Scene::default();
 
-    let mut mesh = TriangleMesh::new(Material {
-        material_type: MaterialType::Lambertian,
-        albedo: Vec3::new(0.3, 0.4, 0.5),
-    });
+    let mut mesh = TriangleMesh::new(Material::lambertian(Vec3::new(0.3, 0.4, 0.5)));
 
     mesh.add_vertex(Vec3::new(0.1, -0.5, -0.6));
     mesh.add_vertex(Vec3::new(0.5, -0.5, -0.6));
@@ -55,10 +131,7 @@ fn main() -> Result<(), io::Error> {
 
     scene.add_mesh(mesh);
 
-    let mut mirror = TriangleMesh::new(Material {
-        material_type: MaterialType::Metal,
-        albedo: Vec3::new(0.9, 0.8, 0.85),
-    });
+    let mut mirror = TriangleMesh::new(Material::metal(Vec3::new(0.9, 0.8, 0.85), 0.0));
 
     mirror.add_vertex(Vec3::new(-1.5, -0.5, -1.5));
     mirror.add_vertex(Vec3::new(-1., -0.5, -1.));
@@ -77,10 +150,7 @@ fn main() -> Result<(), io::Error> {
 
     scene.add_mesh(mirror);
 
-    let mut floor = TriangleMesh::new(Material {
-        material_type: MaterialType::Lambertian,
-        albedo: Vec3::new(0.7, 0.8, 0.5),
-    });
+    let mut floor = TriangleMesh::new(Material::lambertian(Vec3::new(0.7, 0.8, 0.5)));
 
     floor.add_vertex(Vec3::new(-555., -0.51, 5.)); // close left 0 
     floor.add_vertex(Vec3::new(555., -0.51, 5.)); // close right 1
@@ -92,10 +162,7 @@ fn main() -> Result<(), io::Error> {
 
     scene.add_mesh(floor);
 
-    let mut tinybox = TriangleMesh::new(Material {
-        material_type: MaterialType::Emissive,
-        albedo: Vec3::new(4.0, 1.0, 1.0),
-    });
+    let mut tinybox = TriangleMesh::new(Material::emissive(Vec3::new(4.0, 1.0, 1.0)));
 
     tinybox.add_vertex(Vec3::new(0.3, -0.5, -0.65));
     tinybox.add_vertex(Vec3::new(0.4, -0.5, -0.65));
@@ -122,18 +189,20 @@ fn main() -> Result<(), io::Error> {
 
     scene.add_mesh(tinybox);
 
-    let raytracer = RayTracer::new(&bmp_canvas, 2.0, 1.0);
-
-    let start_time = SystemTime::now();
-
-    raytracer.draw(&mut bmp_canvas, &scene, 15, 20);
-
-    println!(
-        "rendered in {} ms",
-        start_time.elapsed().unwrap().as_millis()
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        90.0,
     );
 
-    bmp_canvas.save_image("examples/test.bmp")?;
-
-    Ok(())
+    SceneDescription {
+        scene,
+        camera,
+        settings: RenderSettings::default(),
+        width,
+        height,
+        output: String::from("examples/test.bmp"),
+        sample_map: None,
+    }
 }
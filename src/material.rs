@@ -1,69 +1,569 @@
-use crate::{hittable::HitInfo, ray::Ray, vec3::Vec3};
+use core::f32;
+use std::sync::Arc;
 
-#[derive(Default, Clone, Copy)]
+use crate::{
+    bsdf::{Bsdf, BsdfSample, Frame, reflect, same_hemisphere},
+    hittable::HitInfo,
+    ray::Ray,
+    sampling::{cosine_hemisphere, cosine_hemisphere_pdf},
+    vec3::Vec3,
+};
+
+// What a surface looks like, how it scatters light and how much light it gives off
+#[derive(Clone)]
 pub struct Material {
-    pub material_type: MaterialType,
-    pub albedo: Vec3,
+    // None for surfaces that absorb everything, like the lights
+    pub bsdf: Option<Arc<dyn Bsdf + Send + Sync>>,
+    pub emission: Vec3,
+}
+
+// A scattered ray, with the same weight and pdf as the BsdfSample it came from
+pub struct Scatter {
+    pub ray: Ray,
+    pub weight: Vec3,
+    pub pdf: f32,
+    pub specular: bool,
 }
 
 impl Material {
-    pub fn scatter(
-        &self,
-        ray: Ray,
-        hit_info: &HitInfo,
-        attenuation_out: &mut Vec3,
-        scatter_out: &mut Ray,
-    ) -> bool {
-        return match self.material_type {
-            MaterialType::Lambertian => {
-                self.scatter_lambertian(hit_info, attenuation_out, scatter_out)
-            }
-            MaterialType::Metal => self.scatter_metal(ray, hit_info, attenuation_out, scatter_out),
-            MaterialType::Emissive => self.scatter_emissive(),
+    pub fn new(bsdf: impl Bsdf + Send + Sync + 'static) -> Material {
+        Material {
+            bsdf: Some(Arc::new(bsdf)),
+            emission: Vec3::default(),
+        }
+    }
+
+    pub fn lambertian(albedo: Vec3) -> Material {
+        Material::new(Lambertian { albedo })
+    }
+
+    pub fn metal(albedo: Vec3, roughness: f32) -> Material {
+        Material::new(Metal { albedo, roughness })
+    }
+
+    pub fn dielectric(ior: f32, tint: Vec3) -> Material {
+        Material::new(Dielectric { ior, tint })
+    }
+
+    pub fn emissive(emission: Vec3) -> Material {
+        Material {
+            bsdf: None,
+            emission,
+        }
+    }
+
+    // Continues the path arriving along `ray`, None if it's absorbed. `uc` and `u` are uniform in
+    // [0, 1) and passed on to the BSDF's sample
+    pub fn scatter(&self, ray: Ray, hit_info: &HitInfo, uc: f32, u: [f32; 2]) -> Option<Scatter> {
+        let bsdf = self.bsdf.as_ref()?;
+        let frame = shading_frame(hit_info);
+        let wo = -ray.dir() / ray.dir().magnitude();
+
+        let sample = bsdf.sample(frame.to_local(wo), uc, u)?;
+        let wi = frame.to_world(sample.wi);
+        if !agrees_with_geometry(hit_info, &frame, wo, wi) {
+            return None;
+        }
+
+        Some(Scatter {
+            ray: Ray::new(hit_info.point, wi),
+            weight: sample.weight,
+            pdf: sample.pdf,
+            specular: sample.specular,
+        })
+    }
+
+    // BSDF times the cosine at the surface, for light arriving from the unit vector `light_dir`
+    // and leaving back along `ray`
+    pub fn eval(&self, ray: Ray, hit_info: &HitInfo, light_dir: Vec3) -> Vec3 {
+        let Some(bsdf) = self.bsdf.as_ref() else {
+            return Vec3::default();
         };
+        let frame = shading_frame(hit_info);
+        let wo = -ray.dir() / ray.dir().magnitude();
+        if !agrees_with_geometry(hit_info, &frame, wo, light_dir) {
+            return Vec3::default();
+        }
+
+        bsdf.eval(frame.to_local(wo), frame.to_local(light_dir))
     }
 
-    pub fn emission(&self) -> Vec3 {
-        return match self.material_type {
-            MaterialType::Emissive => self.albedo,
-            _ => Vec3::new(0., 0., 0.),
+    // Density per solid angle with which `scatter` picks the unit vector `dir`
+    pub fn pdf(&self, ray: Ray, hit_info: &HitInfo, dir: Vec3) -> f32 {
+        let Some(bsdf) = self.bsdf.as_ref() else {
+            return 0.0;
         };
+        let frame = shading_frame(hit_info);
+        let wo = -ray.dir() / ray.dir().magnitude();
+        if !agrees_with_geometry(hit_info, &frame, wo, dir) {
+            return 0.0;
+        }
+
+        bsdf.pdf(frame.to_local(wo), frame.to_local(dir))
     }
 
-    fn scatter_lambertian(
-        &self,
-        hit_info: &HitInfo,
-        attenuation_out: &mut Vec3,
-        scatter_out: &mut Ray,
-    ) -> bool {
-        let bounce_dir = hit_info.normal + Vec3::random_unit();
-        *scatter_out = Ray::new(hit_info.point, bounce_dir);
-        *attenuation_out = self.albedo;
-        true
+    // Whether lights can't be sampled for the surface, since it has nothing but specular
+    // lobes or doesn't scatter at all
+    pub fn is_specular(&self) -> bool {
+        self.bsdf.as_ref().is_none_or(|bsdf| bsdf.is_specular())
     }
+}
 
-    fn scatter_metal(
-        &self,
-        ray: Ray,
-        hit_info: &HitInfo,
-        attenuation_out: &mut Vec3,
-        scatter_out: &mut Ray,
-    ) -> bool {
-        let reflect_dir = ray.dir() - 2.0 * ray.dir().dot(hit_info.normal) * hit_info.normal;
-        *scatter_out = Ray::new(hit_info.point, reflect_dir);
-        *attenuation_out = self.albedo;
-        true
+// The frame's normal points out of the surface, whichever side the ray hit
+fn shading_frame(hit_info: &HitInfo) -> Frame {
+    if hit_info.front_face {
+        Frame::new(hit_info.shading_normal)
+    } else {
+        Frame::new(-hit_info.shading_normal)
+    }
+}
+
+// Whether the world space directions `wo` and `wi` are on the same side of the true surface
+// exactly when they are in the shading frame. A smoothed normal can put a direction above the
+// shaded surface but below the real one, these are cut off so light doesn't leak through
+fn agrees_with_geometry(hit_info: &HitInfo, frame: &Frame, wo: Vec3, wi: Vec3) -> bool {
+    let reflects = wo.dot(hit_info.normal) * wi.dot(hit_info.normal) > 0.0;
+    reflects == same_hemisphere(frame.to_local(wo), frame.to_local(wi))
+}
+
+// Flips both directions to the outside of the surface, for two sided BSDFs
+fn upper_hemisphere(wo: Vec3, wi: Vec3) -> (Vec3, Vec3) {
+    if wo.z < 0.0 {
+        (Vec3::new(wo.x, wo.y, -wo.z), Vec3::new(wi.x, wi.y, -wi.z))
+    } else {
+        (wo, wi)
+    }
+}
+
+// Ideal diffuse reflection, the same from both sides
+pub struct Lambertian {
+    pub albedo: Vec3,
+}
+
+impl Bsdf for Lambertian {
+    fn eval(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        if !same_hemisphere(wo, wi) {
+            return Vec3::default();
+        }
+        self.albedo * (wi.z.abs() / f32::consts::PI)
+    }
+
+    fn sample(&self, wo: Vec3, _uc: f32, u: [f32; 2]) -> Option<BsdfSample> {
+        let mut wi = cosine_hemisphere(u);
+        if wo.z < 0.0 {
+            wi.z = -wi.z;
+        }
+
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            wi,
+            weight: self.albedo,
+            pdf,
+            specular: false,
+        })
     }
 
-    fn scatter_emissive(&self) -> bool {
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f32 {
+        if !same_hemisphere(wo, wi) {
+            return 0.0;
+        }
+        cosine_hemisphere_pdf(wi.z.abs())
+    }
+
+    fn is_specular(&self) -> bool {
         false
     }
 }
 
-#[derive(Default, Clone, Copy)]
-pub enum MaterialType {
-    Lambertian,
-    #[default]
-    Metal,
-    Emissive,
+// Conductor with the albedo standing in for its Fresnel reflectance. Rough metals are GGX
+// microfacet surfaces, roughness 0 is a perfect mirror and 1 close to diffuse
+pub struct Metal {
+    pub albedo: Vec3,
+    pub roughness: f32,
+}
+
+impl Bsdf for Metal {
+    // D * G2 * F / (4 * cos_o)
+    fn eval(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        if self.is_specular() || !same_hemisphere(wo, wi) {
+            return Vec3::default();
+        }
+        let (wo, wi) = upper_hemisphere(wo, wi);
+
+        let mut microfacet = wo + wi;
+        microfacet = microfacet / microfacet.magnitude();
+
+        let alpha = self.roughness * self.roughness;
+        let distribution = ggx_d(microfacet, alpha);
+        let masking_shadowing = ggx_smith_g2(wo, wi, alpha);
+
+        self.albedo * (distribution * masking_shadowing / (4.0 * wo.z))
+    }
+
+    fn sample(&self, wo: Vec3, _uc: f32, u: [f32; 2]) -> Option<BsdfSample> {
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        if self.is_specular() {
+            return Some(BsdfSample {
+                wi: reflect(wo),
+                weight: self.albedo,
+                pdf: 1.0,
+                specular: true,
+            });
+        }
+
+        // Reflect off a microfacet normal drawn from the GGX distribution of visible normals
+        let (wo_up, _) = upper_hemisphere(wo, wo);
+        let alpha = self.roughness * self.roughness;
+        let microfacet = sample_ggx_visible_normal(wo_up, alpha, u[0], u[1]);
+        let wi_up = 2.0 * wo_up.dot(microfacet) * microfacet - wo_up;
+
+        // Reflected into the surface, the ray is absorbed
+        if wi_up.z <= 0.0 {
+            return None;
+        }
+
+        // With visible normal sampling the D term and cosines cancel, leaving G2 / G1
+        let masking = ggx_smith_g1(wo_up, alpha);
+        let masking_shadowing = ggx_smith_g2(wo_up, wi_up, alpha);
+
+        let (_, wi) = upper_hemisphere(wo, wi_up);
+        Some(BsdfSample {
+            wi,
+            weight: self.albedo * (masking_shadowing / masking),
+            pdf: self.pdf(wo, wi),
+            specular: false,
+        })
+    }
+
+    // Visible normal sampling picks the microfacet normal with density G1 * D * (wo.m) / cos_o,
+    // reflecting turns that into G1 * D / (4 * cos_o) per solid angle of the reflected direction
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f32 {
+        if self.is_specular() || !same_hemisphere(wo, wi) {
+            return 0.0;
+        }
+        let (wo, wi) = upper_hemisphere(wo, wi);
+
+        let mut microfacet = wo + wi;
+        microfacet = microfacet / microfacet.magnitude();
+
+        let alpha = self.roughness * self.roughness;
+        ggx_smith_g1(wo, alpha) * ggx_d(microfacet, alpha) / (4.0 * wo.z)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.roughness <= 0.0
+    }
+}
+
+// Glass, water and the like. `ior` is the index of refraction relative to air, the tint only
+// colors light passing through
+pub struct Dielectric {
+    pub ior: f32,
+    pub tint: Vec3,
+}
+
+impl Bsdf for Dielectric {
+    fn eval(&self, _wo: Vec3, _wi: Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    // Picks between reflection and refraction with probability given by the Fresnel
+    // reflectance, so no energy is lost
+    fn sample(&self, wo: Vec3, uc: f32, _u: [f32; 2]) -> Option<BsdfSample> {
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        // Ratio of the refractive indices on the incident and transmitted side, with the
+        // normal turned to the side `wo` is on
+        let entering = wo.z > 0.0;
+        let eta = if entering { 1.0 / self.ior } else { self.ior };
+        let normal = Vec3::new(0.0, 0.0, f32::copysign(1.0, wo.z));
+
+        let reflectance = fresnel(wo.z.abs(), eta);
+
+        match refract(-wo, normal, eta) {
+            Some(wi) if uc >= reflectance => Some(BsdfSample {
+                wi,
+                weight: self.tint,
+                pdf: 1.0 - reflectance,
+                specular: true,
+            }),
+            // Total internal reflection, or the Fresnel term picked reflection
+            _ => Some(BsdfSample {
+                wi: reflect(wo),
+                weight: Vec3::new(1.0, 1.0, 1.0),
+                pdf: reflectance,
+                specular: true,
+            }),
+        }
+    }
+
+    fn pdf(&self, _wo: Vec3, _wi: Vec3) -> f32 {
+        0.0
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+// Samples a microfacet normal seen from `wo` (local frame, normal is +z) from the isotropic
+// GGX distribution of visible normals (Heitz 2018)
+fn sample_ggx_visible_normal(wo: Vec3, alpha: f32, u1: f32, u2: f32) -> Vec3 {
+    // Stretch the view vector into the configuration where the microsurface is a hemisphere
+    let mut view = Vec3::new(alpha * wo.x, alpha * wo.y, wo.z);
+    view = view / view.magnitude();
+
+    let len_sq = view.x * view.x + view.y * view.y;
+    let t1 = if len_sq > 0.0 {
+        Vec3::new(-view.y, view.x, 0.0) / f32::sqrt(len_sq)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = view.cross(t1);
+
+    // Uniform point on the projected disk, squashed onto the visible half of it
+    let r = f32::sqrt(u1);
+    let phi = 2.0 * f32::consts::PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + view.z);
+    let p2 = (1.0 - s) * f32::sqrt(1.0 - p1 * p1) + s * r * phi.sin();
+    let p3 = f32::sqrt(f32::max(0.0, 1.0 - p1 * p1 - p2 * p2));
+
+    let hemisphere_normal = p1 * t1 + p2 * t2 + p3 * view;
+
+    // Unstretch back to the GGX configuration
+    let mut normal = Vec3::new(
+        alpha * hemisphere_normal.x,
+        alpha * hemisphere_normal.y,
+        f32::max(0.0, hemisphere_normal.z),
+    );
+    normal = normal / normal.magnitude();
+    normal
+}
+
+// Density of microfacet normals, `normal` in the local frame
+fn ggx_d(normal: Vec3, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let denom = normal.z * normal.z * (alpha2 - 1.0) + 1.0;
+
+    alpha2 / (f32::consts::PI * denom * denom)
+}
+
+// Smith's auxiliary function for GGX, `w` in the local frame
+fn ggx_lambda(w: Vec3, alpha: f32) -> f32 {
+    let cos2 = w.z * w.z;
+    let tan2 = f32::max(0.0, 1.0 - cos2) / cos2;
+
+    0.5 * (f32::sqrt(1.0 + alpha * alpha * tan2) - 1.0)
+}
+
+// Fraction of microfacets facing `w` that aren't hidden behind other microfacets
+fn ggx_smith_g1(w: Vec3, alpha: f32) -> f32 {
+    1.0 / (1.0 + ggx_lambda(w, alpha))
+}
+
+// Height correlated masking-shadowing, the fraction visible from both directions
+fn ggx_smith_g2(wo: Vec3, wi: Vec3, alpha: f32) -> f32 {
+    1.0 / (1.0 + ggx_lambda(wo, alpha) + ggx_lambda(wi, alpha))
+}
+
+// Bends the unit vector `dir` through a surface facing against it with Snell's law,
+// None on total internal reflection
+fn refract(dir: Vec3, normal: Vec3, eta: f32) -> Option<Vec3> {
+    let cos_i = -dir.dot(normal);
+    let sin2_t = eta * eta * f32::max(0.0, 1.0 - cos_i * cos_i);
+
+    if sin2_t > 1.0 {
+        return None;
+    }
+
+    let cos_t = f32::sqrt(1.0 - sin2_t);
+    Some(eta * dir + (eta * cos_i - cos_t) * normal)
+}
+
+// Fraction of unpolarized light reflected by a dielectric boundary, averaging the s and p
+// polarized Fresnel equations. `cos_i` is on the incident side
+fn fresnel(cos_i: f32, eta: f32) -> f32 {
+    let sin2_t = eta * eta * f32::max(0.0, 1.0 - cos_i * cos_i);
+
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = f32::sqrt(1.0 - sin2_t);
+    let r_parallel = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let r_perpendicular = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(x: f32, y: f32, z: f32) -> Vec3 {
+        let v = Vec3::new(x, y, z);
+        v / v.magnitude()
+    }
+
+    #[test]
+    fn metal_samples_agree_with_eval_and_pdf() {
+        let outgoing = [
+            unit(0.0, 0.0, 1.0),
+            unit(0.3, -0.2, 0.9),
+            unit(-0.9, 0.1, 0.2),
+            // From below, as seen from inside a closed mesh
+            unit(0.4, 0.5, -0.6),
+        ];
+
+        for roughness in [0.1, 0.5, 0.9] {
+            let metal = Metal {
+                albedo: Vec3::new(0.9, 0.6, 0.3),
+                roughness,
+            };
+
+            for wo in outgoing {
+                let mut samples = 0;
+                for i in 0..16 {
+                    for j in 0..16 {
+                        let u = [(i as f32 + 0.5) / 16.0, (j as f32 + 0.5) / 16.0];
+                        let Some(sample) = metal.sample(wo, 0.5, u) else {
+                            continue;
+                        };
+                        samples += 1;
+
+                        assert!(same_hemisphere(wo, sample.wi));
+                        assert!(!sample.specular);
+
+                        let pdf = metal.pdf(wo, sample.wi);
+                        assert!(pdf > 0.0);
+                        assert!((sample.pdf - pdf).abs() <= 1e-4 * pdf);
+
+                        let expected = metal.eval(wo, sample.wi) / pdf;
+                        assert!(
+                            (sample.weight - expected).magnitude() <= 1e-3 * expected.magnitude(),
+                            "{:?} != {expected:?} for {wo:?}, roughness {roughness}",
+                            sample.weight
+                        );
+                    }
+                }
+                assert!(samples > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn lambertian_samples_agree_with_eval_and_pdf() {
+        let lambertian = Lambertian {
+            albedo: Vec3::new(0.2, 0.5, 0.8),
+        };
+
+        for wo in [
+            unit(0.0, 0.0, 1.0),
+            unit(0.7, 0.1, 0.1),
+            unit(0.4, 0.5, -0.6),
+        ] {
+            for i in 0..16 {
+                for j in 0..16 {
+                    let u = [(i as f32 + 0.5) / 16.0, (j as f32 + 0.5) / 16.0];
+                    let sample = lambertian.sample(wo, 0.5, u).unwrap();
+
+                    assert!(same_hemisphere(wo, sample.wi));
+                    assert!(!sample.specular);
+                    assert!((sample.wi.magnitude() - 1.0).abs() < 1e-5);
+
+                    let pdf = lambertian.pdf(wo, sample.wi);
+                    assert!(pdf > 0.0);
+                    assert!((sample.pdf - pdf).abs() <= 1e-5 * pdf);
+
+                    let expected = lambertian.eval(wo, sample.wi) / pdf;
+                    assert!(
+                        (sample.weight - expected).magnitude() <= 1e-4,
+                        "{:?} != {expected:?} for {wo:?}",
+                        sample.weight
+                    );
+                }
+            }
+
+            // Nothing goes through
+            let through = Vec3::new(0.0, 0.0, -wo.z.signum());
+            assert_eq!(lambertian.pdf(wo, through), 0.0);
+            assert_eq!(lambertian.eval(wo, through).magnitude(), 0.0);
+        }
+    }
+
+    #[test]
+    fn dielectric_samples_pick_each_lobe_by_its_fresnel_weight() {
+        let tint = Vec3::new(0.9, 0.8, 1.0);
+        let glass = Dielectric { ior: 1.5, tint };
+
+        // Entering from outside, leaving from inside and past the critical angle inside
+        for wo in [
+            unit(0.0, 0.0, 1.0),
+            unit(0.6, 0.2, 0.5),
+            unit(0.3, -0.1, -0.9),
+            unit(0.9, 0.0, -0.2),
+        ] {
+            let eta = if wo.z > 0.0 { 1.0 / 1.5 } else { 1.5 };
+            let reflectance = fresnel(wo.z.abs(), eta);
+
+            const N: usize = 1000;
+            let mut reflected = 0;
+            for i in 0..N {
+                let sample = glass
+                    .sample(wo, (i as f32 + 0.5) / N as f32, [0.5, 0.5])
+                    .unwrap();
+                assert!(sample.specular);
+                assert!((sample.wi.magnitude() - 1.0).abs() < 1e-5);
+
+                // Delta lobes, which eval and pdf leave to sample
+                assert_eq!(glass.eval(wo, sample.wi).magnitude(), 0.0);
+                assert_eq!(glass.pdf(wo, sample.wi), 0.0);
+
+                // Weight times pdf is what the lobe carries, R for the mirror direction and
+                // (1 - R) times the tint through the surface
+                let carried = sample.weight * sample.pdf;
+                if same_hemisphere(wo, sample.wi) {
+                    reflected += 1;
+                    assert!((sample.wi - reflect(wo)).magnitude() < 1e-6);
+                    assert!((carried - Vec3::new(1.0, 1.0, 1.0) * reflectance).magnitude() < 1e-5);
+                } else {
+                    // Snell's law, sin_t = eta sin_i, with the tangent direction flipped
+                    let sin_i = Vec3::new(wo.x, wo.y, 0.0);
+                    let sin_t = Vec3::new(sample.wi.x, sample.wi.y, 0.0);
+                    assert!((sin_t + eta * sin_i).magnitude() < 1e-5);
+                    assert!((carried - tint * (1.0 - reflectance)).magnitude() < 1e-5);
+                }
+            }
+
+            let fraction = reflected as f32 / N as f32;
+            assert!(
+                (fraction - reflectance).abs() <= 1.0 / N as f32,
+                "{fraction} != {reflectance} for {wo:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn fresnel_reflects_everything_past_the_critical_angle() {
+        // From glass into air, eta = 1.5, the critical angle is at cos_i = sqrt(1 - 1 / 1.5^2)
+        let critical = f32::sqrt(1.0 - 1.0 / (1.5 * 1.5));
+        assert_eq!(fresnel(0.1, 1.5), 1.0);
+        assert_eq!(fresnel(0.0, 1.5), 1.0);
+        assert_eq!(fresnel(critical - 1e-3, 1.5), 1.0);
+        assert!(fresnel(critical + 1e-2, 1.5) < 1.0);
+
+        // ((1 - 1.5) / (1 + 1.5))^2 at normal incidence, from either side
+        assert!((fresnel(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-5);
+        assert!((fresnel(1.0, 1.5) - 0.04).abs() < 1e-5);
+    }
 }
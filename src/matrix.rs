@@ -0,0 +1,202 @@
+// Small fixed size matrices for transforms. Both are row major and multiply column vectors, so
+// `a * b` applies `b` first.
+
+use std::ops::Mul;
+
+use crate::{ray::Point3, vec3::Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat3 {
+    pub rows: [[f32; 3]; 3],
+}
+
+impl Mat3 {
+    pub const IDENTITY: Mat3 = Mat3 {
+        rows: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    };
+
+    pub fn from_cols(x: Vec3, y: Vec3, z: Vec3) -> Mat3 {
+        Mat3 {
+            rows: [[x.x, y.x, z.x], [x.y, y.y, z.y], [x.z, y.z, z.z]],
+        }
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.rows;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    pub fn transpose(&self) -> Mat3 {
+        let m = &self.rows;
+        Mat3 {
+            rows: [0, 1, 2].map(|i| [m[0][i], m[1][i], m[2][i]]),
+        }
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = &self.rows;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // The adjugate over the determinant, None for singular matrices
+    pub fn inverse(&self) -> Option<Mat3> {
+        let det = self.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+
+        // Cofactor (i, j) is the 2x2 determinant left after removing row i and column j, the
+        // cyclic indices take care of the sign
+        let m = &self.rows;
+        let cofactor = |i: usize, j: usize| {
+            let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+            let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+
+        Some(Mat3 {
+            rows: [0, 1, 2].map(|i| [0, 1, 2].map(|j| cofactor(j, i) / det)),
+        })
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, other: Mat3) -> Mat3 {
+        Mat3 {
+            rows: [0, 1, 2].map(|i| {
+                [0, 1, 2].map(|j| (0..3).map(|k| self.rows[i][k] * other.rows[k][j]).sum())
+            }),
+        }
+    }
+}
+
+// Affine transform, points have an implicit w of 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub rows: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        rows: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    // `linear` followed by `translation`
+    pub fn from_affine(linear: Mat3, translation: Vec3) -> Mat4 {
+        let [a, b, c] = linear.rows;
+        Mat4 {
+            rows: [
+                [a[0], a[1], a[2], translation.x],
+                [b[0], b[1], b[2], translation.y],
+                [c[0], c[1], c[2], translation.z],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    // The upper left 3x3, everything but the translation
+    pub fn linear(&self) -> Mat3 {
+        Mat3 {
+            rows: [0, 1, 2].map(|i| [self.rows[i][0], self.rows[i][1], self.rows[i][2]]),
+        }
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        let m = &self.rows;
+        Point3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+
+    // Directions ignore the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.rows;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.rows[i][k] * other.rows[k][j]).sum();
+            }
+        }
+
+        Mat4 { rows }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Mat3, b: Mat3) {
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!(
+                    (a.rows[i][j] - b.rows[i][j]).abs() < 1e-5,
+                    "{a:?} != {b:?} at ({i}, {j})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let m = Mat3 {
+            rows: [[2.0, -1.0, 0.5], [0.3, 4.0, 1.0], [-1.0, 0.0, 3.0]],
+        };
+        let inverse = m.inverse().unwrap();
+
+        assert_close(m * inverse, Mat3::IDENTITY);
+        assert_close(inverse * m, Mat3::IDENTITY);
+        assert!((m.determinant() * inverse.determinant() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn singular_matrix_has_no_inverse() {
+        let m = Mat3 {
+            rows: [[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [0.0, 1.0, 1.0]],
+        };
+        assert!(m.inverse().is_none());
+    }
+
+    #[test]
+    fn affine_parts_round_trip() {
+        let linear = Mat3 {
+            rows: [[1.0, 2.0, 0.0], [0.0, 1.0, 0.0], [0.5, 0.0, 2.0]],
+        };
+        let m = Mat4::from_affine(linear, Vec3::new(1.0, -2.0, 3.0));
+        assert_eq!(m.linear(), linear);
+
+        let p = m.transform_point(Point3::new(1.0, 1.0, 1.0));
+        assert_eq!((p.x, p.y, p.z), (4.0, -1.0, 5.5));
+
+        // Products compose the same way in both sizes
+        let other = Mat4::from_affine(linear.transpose(), Vec3::new(0.0, 1.0, 0.0));
+        assert_close((m * other).linear(), linear * linear.transpose());
+    }
+}
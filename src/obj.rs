@@ -0,0 +1,478 @@
+// Wavefront OBJ import, along with the MTL material libraries it references.
+//
+// Every object, group or material change in the file becomes its own TriangleMesh, since a mesh
+// has a single material. Polygons are triangulated as fans, statements that don't describe
+// geometry or materials (smoothing groups, lines, free form surfaces, texture maps) are skipped.
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crate::{
+    material::Material,
+    scene_file::{Line, ParseError, Token},
+    triangle_mesh::TriangleMesh,
+    vec3::Vec3,
+};
+
+// Color of faces without a material, and of materials missing from every library
+const DEFAULT_ALBEDO: Vec3 = Vec3 {
+    x: 0.8,
+    y: 0.8,
+    z: 0.8,
+};
+
+pub fn load_obj(filename: &str) -> Result<Vec<TriangleMesh>, io::Error> {
+    let text = fs::read_to_string(filename).map_err(|err| file_error(filename, err))?;
+    let obj = parse_obj(&text).map_err(|err| parse_error(filename, err))?;
+
+    let dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+    let mut materials = HashMap::new();
+
+    for library in &obj.material_libs {
+        let library_path = dir.join(library);
+        let library_name = library_path.to_string_lossy().into_owned();
+
+        let text =
+            fs::read_to_string(&library_path).map_err(|err| file_error(&library_name, err))?;
+        let library = parse_mtl(&text).map_err(|err| parse_error(&library_name, err))?;
+
+        materials.extend(library);
+    }
+
+    Ok(obj.build_meshes(&materials))
+}
+
+fn file_error(filename: &str, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{filename}: {err}"))
+}
+
+fn parse_error(filename: &str, err: ParseError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{filename}:{err}"))
+}
+
+// One corner of a face, as indices into the position, texcoord and normal lists
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Corner {
+    position: usize,
+    texcoord: Option<usize>,
+    normal: Option<usize>,
+}
+
+#[derive(Default)]
+struct Group {
+    material: Option<String>,
+    triangles: Vec<[Corner; 3]>,
+}
+
+#[derive(Default)]
+struct ObjData {
+    positions: Vec<Vec3>,
+    texcoords: Vec<[f32; 2]>,
+    normals: Vec<Vec3>,
+    groups: Vec<Group>,
+    material_libs: Vec<String>,
+}
+
+fn parse_obj(text: &str) -> Result<ObjData, ParseError> {
+    let mut obj = ObjData::default();
+    let mut group = Group::default();
+
+    for (line_idx, line_text) in text.lines().enumerate() {
+        let mut line = Line::new(line_idx + 1, line_text);
+
+        let Some(keyword) = line.keyword() else {
+            continue;
+        };
+
+        match keyword.text {
+            "v" => {
+                obj.positions.push(line.next_vec3("vertex position")?);
+                // Some exporters append a vertex color, which we have no use for
+                while line.next_optional().is_some() {}
+            }
+            "vt" => {
+                let u = line.next_f32("texture coordinate")?;
+                let v = match line.next_optional() {
+                    Some(token) => token.parse_f32("texture coordinate")?,
+                    None => 0.0,
+                };
+                obj.texcoords.push([u, v]);
+                line.next_optional();
+            }
+            "vn" => {
+                let normal = line.next_vec3("vertex normal")?;
+                if normal.magnitude() == 0.0 {
+                    return Err(keyword.error("vertex normal has zero length"));
+                }
+                obj.normals.push(normal / normal.magnitude());
+            }
+            "f" => {
+                let mut corners = Vec::new();
+                while let Some(token) = line.next_optional() {
+                    corners.push(parse_corner(token, &obj)?);
+                }
+
+                if corners.len() < 3 {
+                    return Err(keyword.error("face needs at least 3 vertices"));
+                }
+
+                for i in 1..corners.len() - 1 {
+                    group
+                        .triangles
+                        .push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            "o" | "g" => {
+                // The names themselves don't matter, only that a new mesh starts here
+                while line.next_optional().is_some() {}
+                let material = group.material.clone();
+                obj.start_group(&mut group, material);
+            }
+            "usemtl" => {
+                let name = line.next_token("material name")?;
+                obj.start_group(&mut group, Some(name.text.to_string()));
+            }
+            "mtllib" => {
+                obj.material_libs
+                    .push(line.next_token("material library")?.text.to_string());
+                while let Some(library) = line.next_optional() {
+                    obj.material_libs.push(library.text.to_string());
+                }
+            }
+            _ => continue,
+        }
+
+        line.finish()?;
+    }
+
+    let material = group.material.clone();
+    obj.start_group(&mut group, material);
+
+    Ok(obj)
+}
+
+impl ObjData {
+    // Moves the faces collected so far into their own group, later faces use `material`
+    fn start_group(&mut self, group: &mut Group, material: Option<String>) {
+        let finished = std::mem::replace(
+            group,
+            Group {
+                material,
+                triangles: Vec::new(),
+            },
+        );
+
+        if !finished.triangles.is_empty() {
+            self.groups.push(finished);
+        }
+    }
+
+    fn build_meshes(&self, materials: &HashMap<String, Material>) -> Vec<TriangleMesh> {
+        let mut meshes = Vec::with_capacity(self.groups.len());
+
+        for group in &self.groups {
+            let material = group
+                .material
+                .as_ref()
+                .and_then(|name| materials.get(name))
+                .cloned()
+                .unwrap_or_else(|| Material::lambertian(DEFAULT_ALBEDO));
+
+            // Attributes are only kept if every corner in the mesh has them
+            let corners = group.triangles.iter().flatten();
+            let has_normals = corners.clone().all(|corner| corner.normal.is_some());
+            let has_texcoords = corners.clone().all(|corner| corner.texcoord.is_some());
+
+            let mut mesh = TriangleMesh::new(material);
+            let mut vertex_indices: HashMap<Corner, u32> = HashMap::new();
+
+            for triangle in &group.triangles {
+                let [a, b, c] = triangle.map(|corner| self.positions[corner.position]);
+                if (b - a).cross(c - a).magnitude() == 0.0 {
+                    continue;
+                }
+
+                let indices = triangle.map(|corner| {
+                    *vertex_indices.entry(corner).or_insert_with(|| {
+                        mesh.add_vertex(self.positions[corner.position]);
+                        if has_normals {
+                            mesh.add_vertex_normal(self.normals[corner.normal.unwrap()]);
+                        }
+                        if has_texcoords {
+                            mesh.add_texcoord(self.texcoords[corner.texcoord.unwrap()]);
+                        }
+                        mesh.vertex_count() as u32 - 1
+                    })
+                });
+
+                mesh.add_triangle(indices[0], indices[1], indices[2]);
+            }
+
+            if mesh.vertex_count() > 0 {
+                meshes.push(mesh);
+            }
+        }
+
+        meshes
+    }
+}
+
+// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`
+fn parse_corner(token: Token, obj: &ObjData) -> Result<Corner, ParseError> {
+    let mut parts = token.text.split('/');
+
+    let position = parse_index(token, parts.next(), obj.positions.len(), "vertex")?;
+    let texcoord = match parts.next() {
+        None | Some("") => None,
+        Some(part) => Some(parse_index(
+            token,
+            Some(part),
+            obj.texcoords.len(),
+            "texture coordinate",
+        )?),
+    };
+    let normal = match parts.next() {
+        None | Some("") => None,
+        Some(part) => Some(parse_index(token, Some(part), obj.normals.len(), "normal")?),
+    };
+
+    if parts.next().is_some() {
+        return Err(token.error(&format!("malformed face vertex '{}'", token.text)));
+    }
+
+    Ok(Corner {
+        position,
+        texcoord,
+        normal,
+    })
+}
+
+// OBJ indices start at 1, negative indices count back from the last element defined so far
+fn parse_index(
+    token: Token,
+    part: Option<&str>,
+    count: usize,
+    what: &str,
+) -> Result<usize, ParseError> {
+    let index = part
+        .and_then(|part| part.parse::<i64>().ok())
+        .ok_or_else(|| token.error(&format!("malformed face vertex '{}'", token.text)))?;
+
+    let resolved = match index {
+        1.. => index - 1,
+        ..0 => count as i64 + index,
+        0 => -1,
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(token.error(&format!(
+            "{what} index {index} out of range, {count} defined so far"
+        )));
+    }
+
+    Ok(resolved as usize)
+}
+
+// The subset of MTL that maps onto our materials
+struct ObjMaterial {
+    diffuse: Vec3,
+    specular: Vec3,
+    emission: Vec3,
+    transmission: Vec3,
+    dissolve: f32,
+    refraction_index: f32,
+    illumination: u32,
+    metallic: f32,
+    // Either given directly by the PBR extension or derived from the specular exponent
+    roughness: f32,
+}
+
+impl Default for ObjMaterial {
+    fn default() -> ObjMaterial {
+        ObjMaterial {
+            diffuse: DEFAULT_ALBEDO,
+            specular: Vec3::default(),
+            emission: Vec3::default(),
+            transmission: Vec3::new(1.0, 1.0, 1.0),
+            dissolve: 1.0,
+            refraction_index: 1.5,
+            illumination: 2,
+            metallic: 0.0,
+            roughness: 0.0,
+        }
+    }
+}
+
+impl ObjMaterial {
+    fn to_material(&self) -> Material {
+        let max = |color: Vec3| f32::max(color.x, f32::max(color.y, color.z));
+
+        if max(self.emission) > 0.0 {
+            return Material::emissive(self.emission);
+        }
+
+        // Illumination models 4, 6, 7 and 9 are the glass and refraction ones
+        if self.dissolve < 1.0 || matches!(self.illumination, 4 | 6 | 7 | 9) {
+            return Material::dielectric(self.refraction_index, self.transmission);
+        }
+
+        // PBR extension, metals put their color in Kd
+        if self.metallic >= 0.5 {
+            return Material::metal(self.diffuse, self.roughness);
+        }
+
+        // illum 3 turns on ray traced reflections, otherwise go by the stronger lobe
+        if self.illumination == 3 || max(self.specular) > max(self.diffuse) {
+            return Material::metal(self.specular, self.roughness);
+        }
+
+        Material::lambertian(self.diffuse)
+    }
+}
+
+fn parse_mtl(text: &str) -> Result<HashMap<String, Material>, ParseError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, ObjMaterial)> = None;
+
+    for (line_idx, line_text) in text.lines().enumerate() {
+        let mut line = Line::new(line_idx + 1, line_text);
+
+        let Some(keyword) = line.keyword() else {
+            continue;
+        };
+
+        if keyword.text == "newmtl" {
+            let name = line.next_token("material name")?;
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material.to_material());
+            }
+            current = Some((name.text.to_string(), ObjMaterial::default()));
+            line.finish()?;
+            continue;
+        }
+
+        let Some((_, material)) = current.as_mut() else {
+            // Nothing outside of a material means anything to us
+            continue;
+        };
+
+        match keyword.text {
+            "Kd" => material.diffuse = line.next_vec3("diffuse color")?,
+            "Ks" => material.specular = line.next_vec3("specular color")?,
+            "Ke" => material.emission = line.next_vec3("emission color")?,
+            "Tf" => material.transmission = line.next_vec3("transmission filter")?,
+            "d" => material.dissolve = line.next_f32("dissolve")?,
+            "Tr" => material.dissolve = 1.0 - line.next_f32("transparency")?,
+            "illum" => material.illumination = line.next_u32("illumination model")?,
+            "Pm" => material.metallic = line.next_f32("metallic")?,
+            "Pr" => material.roughness = line.next_f32("roughness")?.clamp(0.0, 1.0),
+            "Ni" => {
+                let token = line.peek_token("index of refraction")?;
+                let ior = line.next_f32("index of refraction")?;
+                if ior <= 0.0 {
+                    return Err(token.error("index of refraction must be positive"));
+                }
+                material.refraction_index = ior;
+            }
+            "Ns" => {
+                // Phong exponent to GGX alpha (Walter et al. 2007), roughness is its square root
+                let exponent = f32::max(0.0, line.next_f32("specular exponent")?);
+                let alpha = f32::sqrt(2.0 / (exponent + 2.0));
+                material.roughness = f32::sqrt(alpha);
+            }
+            _ => continue,
+        }
+
+        line.finish()?;
+    }
+
+    if let Some((name, material)) = current {
+        materials.insert(name, material.to_material());
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corner_positions(group: &Group) -> Vec<[usize; 3]> {
+        group
+            .triangles
+            .iter()
+            .map(|triangle| triangle.map(|corner| corner.position))
+            .collect()
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn triangulates_polygons_as_fans() {
+        let obj = parse_obj(&format!("{SQUARE}v 0.5 1.5 0\nf 1 2 3 5 4\n")).unwrap();
+
+        assert_eq!(obj.groups.len(), 1);
+        assert_eq!(
+            corner_positions(&obj.groups[0]),
+            [[0, 1, 2], [0, 2, 4], [0, 4, 3]]
+        );
+    }
+
+    #[test]
+    fn resolves_negative_and_slashed_indices() {
+        let text = format!("{SQUARE}vt 0 0\nvn 0 0 2\nf -4/1/1 -3//1 -2/-1\nv 2 2 2\nf -1 1 2\n");
+        let obj = parse_obj(&text).unwrap();
+
+        let triangles = &obj.groups[0].triangles;
+        assert_eq!(corner_positions(&obj.groups[0]), [[0, 1, 2], [4, 0, 1]]);
+        assert_eq!(triangles[0][0].texcoord, Some(0));
+        assert_eq!(triangles[0][1].texcoord, None);
+        assert_eq!(triangles[0][1].normal, Some(0));
+        assert_eq!(triangles[0][2].normal, None);
+        assert_eq!(obj.normals[0].z, 1.0);
+
+        // Counting back past the first vertex, or forward past the last, is out of range
+        let err = parse_obj(&format!("{SQUARE}f 1 2 -5\n")).err().unwrap();
+        assert_eq!((err.line, err.column), (5, 7));
+        assert!(parse_obj(&format!("{SQUARE}f 1 2 5\n")).is_err());
+        assert!(parse_obj(&format!("{SQUARE}f 0 1 2\n")).is_err());
+    }
+
+    #[test]
+    fn splits_meshes_at_objects_groups_and_materials() {
+        let text = format!(
+            "{SQUARE}f 1 2 3\no first\nf 1 3 4\nusemtl red\nf 1 2 4\ng second\nf 2 3 4\n\
+             g empty\ng third\nusemtl blue\nf 1 2 3\n"
+        );
+        let obj = parse_obj(&text).unwrap();
+
+        let materials: Vec<Option<&str>> = obj
+            .groups
+            .iter()
+            .map(|group| group.material.as_deref())
+            .collect();
+        // Groups keep the material in use, and groups without faces are dropped
+        assert_eq!(
+            materials,
+            [None, None, Some("red"), Some("red"), Some("blue")]
+        );
+        assert!(obj.groups.iter().all(|group| group.triangles.len() == 1));
+    }
+
+    #[test]
+    fn skips_degenerate_faces() {
+        // A zero area face, and a group with nothing but one
+        let text = format!("{SQUARE}v 2 0 0\nf 1 2 3\nf 1 2 5\ng line\nf 1 2 5\n");
+        let meshes = parse_obj(&text).unwrap().build_meshes(&HashMap::new());
+
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].triangles().count(), 1);
+    }
+
+    #[test]
+    fn rejects_non_positive_refraction_index() {
+        let err = parse_mtl("newmtl glass\nd 0.5\nNi 0\n").err().unwrap();
+        assert_eq!((err.line, err.column), (3, 4));
+        assert!(parse_mtl("newmtl glass\nillum 7\nNi -1.5\n").is_err());
+        assert!(parse_mtl("newmtl glass\nillum 7\nNi 1.33\n").is_ok());
+    }
+}
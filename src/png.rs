@@ -0,0 +1,202 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path;
+
+use crate::canvas::Canvas;
+use crate::zlib::{crc32, zlib_compress};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// Writes any canvas as an 8 bit RGB png
+pub fn save_png(canvas: &impl Canvas, filename: &str) -> Result<(), io::Error> {
+    // Create the directory if needed
+    let path = path::Path::new(filename);
+    if let Some(dir_path) = path.parent() {
+        fs::create_dir_all(dir_path)?;
+    }
+    let mut output_file = File::create(filename)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend(canvas.width().to_be_bytes());
+    header.extend(canvas.height().to_be_bytes());
+    // 8 bits per channel, RGB, deflate, adaptive filtering, not interlaced
+    header.extend([8, 2, 0, 0, 0]);
+
+    output_file.write_all(&PNG_SIGNATURE)?;
+    write_chunk(&mut output_file, b"IHDR", &header)?;
+    write_chunk(
+        &mut output_file,
+        b"IDAT",
+        &zlib_compress(&filter_scanlines(canvas)),
+    )?;
+    write_chunk(&mut output_file, b"IEND", &[])?;
+
+    output_file.sync_all()?;
+
+    Ok(())
+}
+
+fn write_chunk(output: &mut impl Write, chunk_type: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    // The CRC covers the chunk type and data, but not the length
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend((data.len() as u32).to_be_bytes());
+    chunk.extend(chunk_type);
+    chunk.extend(data);
+    chunk.extend(crc32(&chunk[4..]).to_be_bytes());
+
+    output.write_all(&chunk)
+}
+
+// Every scanline is stored with whichever filter leaves the smallest residuals,
+// the usual heuristic for picking filters that compress well
+fn filter_scanlines(canvas: &impl Canvas) -> Vec<u8> {
+    let scanline_size = canvas.width() as usize * 3;
+    let mut filtered = Vec::with_capacity((scanline_size + 1) * canvas.height() as usize);
+
+    let mut prev_line = vec![0u8; scanline_size];
+    let mut line = vec![0u8; scanline_size];
+    let mut candidate = vec![0u8; scanline_size];
+    let mut best = vec![0u8; scanline_size];
+
+    for y in 0..canvas.height() {
+        for x in 0..canvas.width() {
+            let pixel = canvas.get_pixel(x, y);
+            let idx = x as usize * 3;
+            line[idx..idx + 3].copy_from_slice(&[pixel.r, pixel.g, pixel.b]);
+        }
+
+        let mut best_filter = 0;
+        let mut best_cost = u64::MAX;
+
+        for filter in 0..=4 {
+            filter_line(filter, &line, &prev_line, &mut candidate);
+
+            // Residuals read as signed bytes, small magnitudes compress best
+            let cost = candidate
+                .iter()
+                .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                .sum();
+
+            if cost < best_cost {
+                best_cost = cost;
+                best_filter = filter;
+                best.copy_from_slice(&candidate);
+            }
+        }
+
+        filtered.push(best_filter);
+        filtered.extend_from_slice(&best);
+
+        std::mem::swap(&mut prev_line, &mut line);
+    }
+
+    filtered
+}
+
+// Residuals of `line` after predicting each byte from its neighbours with PNG filter type `filter`,
+// 0 to 4 for none, left, up, average and Paeth
+fn filter_line(filter: u8, line: &[u8], prev_line: &[u8], residuals: &mut [u8]) {
+    for i in 0..line.len() {
+        let (left, up, up_left) = neighbours(line, prev_line, i);
+
+        residuals[i] = line[i].wrapping_sub(predict(filter, left, up, up_left));
+    }
+}
+
+// The byte one pixel to the left, above, and above left of byte `i`, 0 past the edge
+fn neighbours(line: &[u8], prev_line: &[u8], i: usize) -> (u8, u8, u8) {
+    let left = if i >= 3 { line[i - 3] } else { 0 };
+    let up_left = if i >= 3 { prev_line[i - 3] } else { 0 };
+    (left, prev_line[i], up_left)
+}
+
+fn predict(filter: u8, left: u8, up: u8, up_left: u8) -> u8 {
+    match filter {
+        0 => 0,
+        1 => left,
+        2 => up,
+        3 => ((left as u16 + up as u16) / 2) as u8,
+        _ => paeth(left, up, up_left),
+    }
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let dist_left = (estimate - left as i16).abs();
+    let dist_up = (estimate - up as i16).abs();
+    let dist_up_left = (estimate - up_left as i16).abs();
+
+    if dist_left <= dist_up && dist_left <= dist_up_left {
+        left
+    } else if dist_up <= dist_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::{bmp::BmpCanvas, canvas::Pixel, rng::Pcg32};
+
+    // What a PNG decoder does: adds the prediction back, from bytes it has already decoded
+    fn unfilter_line(filter: u8, residuals: &[u8], prev_line: &[u8]) -> Vec<u8> {
+        let mut line = vec![0; residuals.len()];
+        for i in 0..residuals.len() {
+            let (left, up, up_left) = neighbours(&line, prev_line, i);
+            line[i] = residuals[i].wrapping_add(predict(filter, left, up, up_left));
+        }
+        line
+    }
+
+    #[test]
+    fn every_filter_type_reverses() {
+        let mut rng = Pcg32::new(5, 0);
+        let prev_line: Vec<u8> = (0..30).map(|_| rng.random()).collect();
+        let line: Vec<u8> = (0..30).map(|_| rng.random()).collect();
+
+        for filter in 0..=4 {
+            let mut residuals = vec![0; line.len()];
+            filter_line(filter, &line, &prev_line, &mut residuals);
+            assert_eq!(unfilter_line(filter, &residuals, &prev_line), line);
+        }
+    }
+
+    #[test]
+    fn filtered_image_decodes_to_the_canvas() {
+        let mut rng = Pcg32::new(6, 0);
+        let (width, height) = (7, 9);
+        let mut canvas = BmpCanvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                // A gradient with some noise, so different lines pick different filters
+                let noise: u8 = rng.random_range(0..(y as u8 * 8 + 1));
+                let value = (x * 20 + y * 3) as u8;
+                canvas.set_pixel(
+                    x,
+                    y,
+                    Pixel {
+                        r: value.wrapping_add(noise),
+                        g: value,
+                        b: 255 - value,
+                    },
+                );
+            }
+        }
+
+        let filtered = filter_scanlines(&canvas);
+        let mut prev_line = vec![0; width as usize * 3];
+        for (y, chunk) in filtered.chunks_exact(width as usize * 3 + 1).enumerate() {
+            let line = unfilter_line(chunk[0], &chunk[1..], &prev_line);
+            for x in 0..width {
+                let pixel = canvas.get_pixel(x, y as u32);
+                let idx = x as usize * 3;
+                assert_eq!(line[idx..idx + 3], [pixel.r, pixel.g, pixel.b]);
+            }
+            prev_line = line;
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{
+    bbox::Bbox,
+    hittable::{HitInfo, Hittable},
+    light::LightShape,
+    material::Material,
+    ray::{Interval, Point3, Ray},
+    vec3::Vec3,
+};
+
+// Parallelogram spanned by two edges from a corner, a cheap floor, wall or area light
+pub struct Quad {
+    corner: Point3,
+    u: Vec3,
+    v: Vec3,
+    // Unit normal, u cross v
+    normal: Vec3,
+    // u cross v divided by its squared length, turns a point on the plane into edge coordinates
+    w: Vec3,
+    material: Material,
+}
+
+impl Quad {
+    pub fn new(corner: Point3, u: Vec3, v: Vec3, material: Material) -> Quad {
+        let n = u.cross(v);
+
+        Quad {
+            corner,
+            u,
+            v,
+            normal: n / n.magnitude(),
+            w: n / n.dot(n),
+            material,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit<'a>(&'a self, ray: Ray, interval: Interval, hit_info_out: &mut HitInfo<'a>) -> bool {
+        let d = self.normal.dot(ray.dir());
+        if d == 0.0 {
+            return false;
+        }
+
+        let t = self.normal.dot(self.corner - ray.origin()) / d;
+        if hit_info_out.t < t || !interval.contains(t) {
+            return false;
+        }
+
+        // Position on the plane in multiples of the two edges
+        let point = ray.at(t);
+        let offset = point - self.corner;
+        let alpha = self.w.dot(offset.cross(self.v));
+        let beta = self.w.dot(self.u.cross(offset));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        hit_info_out.front_face = d < 0.0;
+        hit_info_out.normal = if hit_info_out.front_face {
+            self.normal
+        } else {
+            -self.normal
+        };
+        hit_info_out.shading_normal = hit_info_out.normal;
+        hit_info_out.barycentric = [0.0, 0.0];
+        hit_info_out.t = t;
+        hit_info_out.point = point;
+        hit_info_out.material = &self.material;
+
+        true
+    }
+
+    fn bounds(&self) -> Bbox {
+        let far = self.corner + self.u + self.v;
+        let bounds = Bbox::from_points(self.corner, self.corner + self.u, self.corner + self.v)
+            .union(Bbox::from_points(far, far, far));
+
+        // Padded, since the quad is flat along at least one axis when it's axis aligned
+        let epsilon = 0.0001;
+        let axis = |i: usize| {
+            let interval = bounds.axis_interval(i);
+            Interval::new(interval.get_val(0) - epsilon, interval.get_val(1) + epsilon)
+        };
+
+        Bbox::new(axis(0), axis(1), axis(2))
+    }
+
+    // As two triangles, which is what the light list knows how to sample
+    fn lights(&self) -> Vec<(LightShape, Vec3)> {
+        let emission = self.material.emission;
+        if emission.magnitude() <= 0.0 {
+            return Vec::new();
+        }
+
+        let (a, b, c, d) = (
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.u + self.v,
+            self.corner + self.v,
+        );
+        vec![
+            (LightShape::Triangle([a, b, c]), emission),
+            (LightShape::Triangle([a, c, d]), emission),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-5, "{a:?} != {b:?}");
+    }
+
+    // Skewed, so points inside its bounding box can still be outside it
+    fn skewed_quad(material: Material) -> Quad {
+        Quad::new(
+            Point3::new(1.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            material,
+        )
+    }
+
+    fn grey() -> Material {
+        Material::lambertian(Vec3::new(0.5, 0.5, 0.5))
+    }
+
+    fn hit(quad: &Quad, ray: Ray, interval: Interval) -> Option<HitInfo<'_>> {
+        let mut hit_info = HitInfo {
+            t: f32::INFINITY,
+            ..Default::default()
+        };
+        quad.hit(ray, interval, &mut hit_info).then_some(hit_info)
+    }
+
+    #[test]
+    fn hits_inside_the_edges_only() {
+        let quad = skewed_quad(grey());
+        let everything = Interval::new(0.001, f32::INFINITY);
+
+        for alpha in [-0.1, 0.05, 0.5, 0.95, 1.1] {
+            for beta in [-0.1, 0.05, 0.5, 0.95, 1.1] {
+                let target = quad.corner + alpha * quad.u + beta * quad.v;
+                let ray = Ray::new(target + Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+
+                let inside = (0.0..=1.0).contains(&alpha) && (0.0..=1.0).contains(&beta);
+                let Some(hit_info) = hit(&quad, ray, everything) else {
+                    assert!(!inside, "missed {alpha} {beta}");
+                    continue;
+                };
+                assert!(inside, "hit {alpha} {beta}");
+                assert!((hit_info.t - 3.0).abs() < 1e-5);
+                assert_close(hit_info.point, target);
+                assert!(std::ptr::eq(hit_info.material, &quad.material));
+            }
+        }
+
+        // Running along the plane never hits it
+        let parallel = Ray::new(Point3::new(0.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(hit(&quad, parallel, everything).is_none());
+    }
+
+    #[test]
+    fn normal_faces_the_ray_from_either_side() {
+        let quad = skewed_quad(grey());
+        let everything = Interval::new(0.001, f32::INFINITY);
+
+        let above = Ray::new(Point3::new(2.5, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_info = hit(&quad, above, everything).unwrap();
+        assert!(hit_info.front_face);
+        assert_close(hit_info.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_close(hit_info.shading_normal, hit_info.normal);
+
+        let below = Ray::new(Point3::new(2.5, 0.5, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit_info = hit(&quad, below, everything).unwrap();
+        assert!(!hit_info.front_face);
+        assert_close(hit_info.normal, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn only_hits_within_the_interval() {
+        let quad = skewed_quad(grey());
+        let ray = Ray::new(Point3::new(2.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        assert!(hit(&quad, ray, Interval::new(0.001, 4.9)).is_none());
+        assert!(hit(&quad, ray, Interval::new(5.1, f32::INFINITY)).is_none());
+        assert!(hit(&quad, ray, Interval::new(4.9, 5.1)).is_some());
+
+        // Nor behind something closer that was already found
+        let mut hit_info = HitInfo {
+            t: 4.0,
+            ..Default::default()
+        };
+        assert!(!quad.hit(ray, Interval::new(0.001, f32::INFINITY), &mut hit_info));
+        assert_eq!(hit_info.t, 4.0);
+    }
+
+    #[test]
+    fn bounds_cover_the_corners_with_padding() {
+        let bounds = skewed_quad(grey()).bounds();
+
+        let expected = [(1.0, 4.0), (0.0, 1.0), (0.0, 0.0)];
+        for (axis, (low, high)) in expected.into_iter().enumerate() {
+            let interval = bounds.axis_interval(axis);
+            assert!(interval.get_val(0) < low && interval.get_val(0) > low - 0.001);
+            assert!(interval.get_val(1) > high && interval.get_val(1) < high + 0.001);
+        }
+    }
+
+    #[test]
+    fn lights_are_two_triangles_covering_the_quad() {
+        assert!(skewed_quad(grey()).lights().is_empty());
+
+        let emission = Vec3::new(1.0, 2.0, 3.0);
+        let quad = skewed_quad(Material::emissive(emission));
+        let lights = quad.lights();
+        assert_eq!(lights.len(), 2);
+
+        let corners = [
+            quad.corner,
+            quad.corner + quad.u,
+            quad.corner + quad.u + quad.v,
+            quad.corner + quad.v,
+        ];
+        let mut used = [false; 4];
+        let mut area = 0.0;
+        for (shape, light_emission) in &lights {
+            let LightShape::Triangle([a, b, c]) = *shape else {
+                panic!("not a triangle");
+            };
+            for p in [a, b, c] {
+                let corner = corners
+                    .iter()
+                    .position(|&corner| (corner - p).magnitude() < 1e-6);
+                used[corner.expect("not a corner of the quad")] = true;
+            }
+            area += 0.5 * (b - a).cross(c - a).magnitude();
+            assert_close(*light_emission, emission);
+        }
+        assert_eq!(used, [true; 4]);
+        assert!((area - quad.u.cross(quad.v).magnitude()).abs() < 1e-5);
+    }
+}
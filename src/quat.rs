@@ -0,0 +1,140 @@
+// Unit quaternions for rotations, which compose and interpolate without the drift and gimbal
+// lock of matrices and Euler angles.
+
+use std::ops::Mul;
+
+use crate::{matrix::Mat3, vec3::Vec3};
+
+// x, y and z are the vector part, w the scalar part
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quat {
+    // Counterclockwise looking down `axis` towards the origin, the axis doesn't have to be unit
+    // length
+    pub fn from_axis_angle(axis: Vec3, degrees: f32) -> Quat {
+        let a = axis / axis.magnitude();
+        let (sin, cos) = (degrees.to_radians() / 2.0).sin_cos();
+
+        Quat {
+            x: a.x * sin,
+            y: a.y * sin,
+            z: a.z * sin,
+            w: cos,
+        }
+    }
+
+    pub fn dot(&self, other: Quat) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn normalized(&self) -> Quat {
+        let length = self.dot(*self).sqrt();
+        Quat {
+            x: self.x / length,
+            y: self.y / length,
+            z: self.z / length,
+            w: self.w / length,
+        }
+    }
+
+    pub fn to_mat3(self) -> Mat3 {
+        let Quat { x, y, z, w } = self;
+        Mat3 {
+            rows: [
+                [
+                    1.0 - 2.0 * (y * y + z * z),
+                    2.0 * (x * y - w * z),
+                    2.0 * (x * z + w * y),
+                ],
+                [
+                    2.0 * (x * y + w * z),
+                    1.0 - 2.0 * (x * x + z * z),
+                    2.0 * (y * z - w * x),
+                ],
+                [
+                    2.0 * (x * z - w * y),
+                    2.0 * (y * z + w * x),
+                    1.0 - 2.0 * (x * x + y * y),
+                ],
+            ],
+        }
+    }
+}
+
+// `self * other` rotates by `other` first
+impl Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, other: Quat) -> Quat {
+        Quat {
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-5, "{a:?} != {b:?}");
+    }
+
+    // The inverse rotation, for unit quaternions
+    fn conjugate(q: Quat) -> Quat {
+        Quat {
+            x: -q.x,
+            y: -q.y,
+            z: -q.z,
+            w: q.w,
+        }
+    }
+
+    // q v q*, expanded so it costs two cross products. The reference `to_mat3` is tested against
+    fn rotate(q: Quat, v: Vec3) -> Vec3 {
+        let u = Vec3::new(q.x, q.y, q.z);
+        let t = 2.0 * u.cross(v);
+        v + q.w * t + u.cross(t)
+    }
+
+    #[test]
+    fn rotates_counterclockwise_about_the_axis() {
+        let q = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 2.0), 90.0);
+
+        assert_close(
+            rotate(q, Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        assert_close(
+            rotate(q, Vec3::new(0.0, 0.0, 3.0)),
+            Vec3::new(0.0, 0.0, 3.0),
+        );
+        assert_close(
+            rotate(conjugate(q), rotate(q, Vec3::new(1.0, 2.0, 3.0))),
+            Vec3::new(1.0, 2.0, 3.0),
+        );
+    }
+
+    #[test]
+    fn matrix_and_product_agree_with_rotate() {
+        let a = Quat::from_axis_angle(Vec3::new(1.0, 2.0, -0.5), 37.0);
+        let b = Quat::from_axis_angle(Vec3::new(-0.3, 0.0, 1.0), 120.0);
+        let v = Vec3::new(0.4, -1.5, 2.0);
+
+        assert_close(a.to_mat3().transform_vector(v), rotate(a, v));
+        assert_close(rotate(a * b, v), rotate(a, rotate(b, v)));
+        assert_close(
+            (a.to_mat3() * b.to_mat3()).transform_vector(v),
+            rotate(a * b, v),
+        );
+    }
+}
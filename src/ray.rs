@@ -16,7 +16,7 @@ impl Ray {
     }
 
     pub fn at(&self, t: f32) -> Point3 {
-        return self.origin + t * self.dir;
+        self.origin + t * self.dir
     }
 
     pub fn origin(&self) -> Point3 {
@@ -55,13 +55,21 @@ impl Interval {
     }
 
     pub fn get_val(&self, i: usize) -> f32 {
-        return self.endpoints[i as usize];
+        self.endpoints[i]
+    }
+
+    pub fn size(&self) -> f32 {
+        self.endpoints[1] - self.endpoints[0]
+    }
+
+    pub fn union(&self, other: Interval) -> Interval {
+        Interval::new(
+            f32::min(self.endpoints[0], other.endpoints[0]),
+            f32::max(self.endpoints[1], other.endpoints[1]),
+        )
     }
 }
 
 pub const EMPTY: Interval = Interval {
     endpoints: [f32::INFINITY, f32::NEG_INFINITY],
 };
-pub const ANY: Interval = Interval {
-    endpoints: [f32::NEG_INFINITY, f32::INFINITY],
-};
@@ -1,107 +1,691 @@
-use rand::Rng;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    canvas::{Canvas, to_pixel},
+    camera::Camera,
+    canvas::ToneMapping,
+    film::{Film, PixelStats},
     hittable::{HitInfo, Hittable},
-    ray::{Interval, Point3, Ray},
+    light::LightSample,
+    ray::{Interval, Ray},
+    sampler::{
+        HaltonSampler, IndependentSampler, Sampler, SamplerKind, SobolSampler, StratifiedSampler,
+    },
     triangle_mesh::Scene,
     vec3::Vec3,
 };
 
+pub struct RenderSettings {
+    // The most samples any pixel gets
+    pub samples: i32,
+    // With adaptive sampling, every pixel gets at least this many samples before it may stop
+    pub min_samples: i32,
+    // Pixels stop taking samples once their noise estimate drops below this, 0 gives every
+    // pixel all the samples
+    pub noise_threshold: f32,
+    // Renders progressively in passes of this many samples per pixel, 0 renders all samples in
+    // one go
+    pub pass_samples: i32,
+    // Snapshots are taken after a pass once this long has passed since the last one, zero takes
+    // one after every pass
+    pub snapshot_interval: Duration,
+    pub max_bounces: i16,
+    // Number of worker threads, each one renders a tile at a time
+    pub threads: usize,
+    // Width and height of the square tiles the image is split into
+    pub tile_size: u32,
+    // The same seed always renders the same image, regardless of thread count
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    // Applied when the linear image is turned into 8 bit pixels
+    pub tone_mapping: ToneMapping,
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            samples: 15,
+            min_samples: 16,
+            noise_threshold: 0.0,
+            pass_samples: 0,
+            snapshot_interval: Duration::ZERO,
+            max_bounces: 20,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size: 32,
+            seed: 0,
+            sampler: SamplerKind::default(),
+            tone_mapping: ToneMapping::default(),
+        }
+    }
+}
+
 pub struct RayTracer {
-    viewport_height: f32,
-    viewport_width: f32,
-    focal_len: f32,
-    camera_pos: Point3,
+    camera: Camera,
 }
 
 impl RayTracer {
-    pub fn new(canvas: &impl Canvas, viewport_height: f32, focal_length: f32) -> RayTracer {
-        let viewport_width = viewport_height * (canvas.width() as f32 / canvas.height() as f32);
-
-        // Camera is (initially) positioned at (0, 0, 0)
-        RayTracer {
-            viewport_height: viewport_height,
-            viewport_width: viewport_width,
-            focal_len: focal_length,
-            camera_pos: Point3::new(0.0, 0.0, 0.0),
+    pub fn new(camera: Camera) -> RayTracer {
+        RayTracer { camera }
+    }
+
+    // Renders into the linear film, which is tone mapped separately. `snapshot` is shown the
+    // film between passes along with the samples per pixel taken so far
+    pub fn draw(
+        &self,
+        film: &mut Film,
+        scene: &Scene,
+        settings: &RenderSettings,
+        mut snapshot: impl FnMut(&Film, u32),
+    ) {
+        let snapshot = &mut snapshot;
+        let seed = settings.seed;
+        match settings.sampler {
+            SamplerKind::Independent => self.draw_with(
+                film,
+                scene,
+                settings,
+                IndependentSampler::new(seed),
+                snapshot,
+            ),
+            SamplerKind::Stratified => {
+                let samples = u32::try_from(settings.samples).unwrap_or(1);
+                self.draw_with(
+                    film,
+                    scene,
+                    settings,
+                    StratifiedSampler::new(seed, samples),
+                    snapshot,
+                )
+            }
+            SamplerKind::Halton => {
+                self.draw_with(film, scene, settings, HaltonSampler::new(seed), snapshot)
+            }
+            SamplerKind::Sobol => {
+                self.draw_with(film, scene, settings, SobolSampler::new(seed), snapshot)
+            }
         }
     }
 
-    pub fn draw(&self, canvas: &mut impl Canvas, scene: &Scene, samples: i32, max_bounces: i16) {
-        let x_viewport = Vec3::new(self.viewport_width, 0.0, 0.0);
-        let y_viewport = Vec3::new(0.0, -self.viewport_height, 0.0);
-        let x_delta = x_viewport / canvas.width() as f32;
-        let y_delta = y_viewport / canvas.height() as f32;
-
-        let viewport_top_left = self.camera_pos
-            - Point3::new(0.0, 0.0, self.focal_len)
-            - x_viewport / 2.0
-            - y_viewport / 2.0
-            + x_delta / 2.0
-            + y_delta / 2.0;
-        for y in 0..canvas.height() {
-            for x in 0..canvas.width() {
-                let base_dir = viewport_top_left + (x_delta * x as f32) + (y_delta * y as f32);
-
-                let mut color = Vec3::new(0., 0., 0.);
-                for _ in 0..samples {
-                    let x_offset = rand::rng().random_range(-0.5..0.5);
-                    let y_offset = rand::rng().random_range(-0.5..0.5);
-
-                    let dir = Vec3::new(
-                        base_dir.x + x_delta.x * x_offset,
-                        base_dir.y + y_delta.y * y_offset,
-                        base_dir.z,
-                    );
+    // Renders in passes, each bringing every noisy pixel up to a target sample count. With
+    // adaptive sampling every pixel first takes the minimum number of samples, then the target
+    // grows by `pass_samples`, or doubles without it, until pixels run out of samples
+    fn draw_with<S: Sampler + Clone + Send>(
+        &self,
+        film: &mut Film,
+        scene: &Scene,
+        settings: &RenderSettings,
+        sampler: S,
+        snapshot: &mut impl FnMut(&Film, u32),
+    ) {
+        let max_samples = u32::try_from(settings.samples).unwrap_or(0);
+        let min_samples = u32::try_from(settings.min_samples).unwrap_or(0);
+        let pass_samples = u32::try_from(settings.pass_samples).unwrap_or(0);
+        let adaptive = settings.noise_threshold > 0.0;
 
-                    let ray = Ray::new(self.camera_pos, dir);
+        let mut target = match (adaptive, pass_samples) {
+            (true, _) => u32::clamp(min_samples, 1, u32::max(max_samples, 1)),
+            (false, 0) => max_samples,
+            (false, pass_samples) => u32::min(pass_samples, max_samples),
+        };
+        let mut last_snapshot = Instant::now();
 
-                    color = color + ray_color(ray, scene, max_bounces);
-                }
+        loop {
+            let noisy = noisy_pixels(film, settings.noise_threshold);
+            self.render_round(film, scene, settings, &sampler, &noisy, target);
+
+            if target >= max_samples {
+                break;
+            }
+
+            if last_snapshot.elapsed() >= settings.snapshot_interval {
+                snapshot(film, target);
+                last_snapshot = Instant::now();
+            }
+
+            let next = match pass_samples {
+                0 => target.saturating_mul(2),
+                pass_samples => target.saturating_add(pass_samples),
+            };
+            target = u32::min(next, max_samples);
+        }
+    }
+
+    // Brings every noisy pixel up to `target` samples. Every worker thread gets its own copy of
+    // `sampler`
+    fn render_round<S: Sampler + Clone + Send>(
+        &self,
+        film: &mut Film,
+        scene: &Scene,
+        settings: &RenderSettings,
+        sampler: &S,
+        noisy: &[bool],
+        target: u32,
+    ) {
+        let viewport = self.camera.viewport(film.width(), film.height());
+        let width = film.width();
+
+        let render_pixel = |sampler: &mut S, x: u32, y: u32, mut stats: PixelStats| {
+            if !noisy[(y * width + x) as usize] {
+                return stats;
+            }
+
+            while stats.count < target {
+                // Samples only depend on the pixel and their index, so the image doesn't depend
+                // on which thread rendered it or in what order
+                sampler.start_sample(x, y, stats.count);
+
+                let [x_offset, y_offset] = sampler.get_2d();
+                let [lens_x, lens_y] = sampler.get_2d();
+
+                let ray = viewport.get_ray(
+                    x as f32 + x_offset - 0.5,
+                    y as f32 + y_offset - 0.5,
+                    lens_x,
+                    lens_y,
+                );
+
+                stats.add(ray_color(ray, scene, settings.max_bounces, sampler));
+            }
+
+            stats
+        };
+
+        let tiles = split_tiles(width, film.height(), settings.tile_size);
+        let next_tile = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
 
-                color = color / (samples as f32);
+        // Workers continue from what the film has so far, so it's only updated once they're done
+        let rendered: Vec<(Tile, Vec<PixelStats>)> = thread::scope(|scope| {
+            for _ in 0..usize::max(settings.threads, 1) {
+                let sender = sender.clone();
+                let (tiles, next_tile, render_pixel) = (&tiles, &next_tile, &render_pixel);
+                let film = &*film;
+                let mut sampler = sampler.clone();
 
-                canvas.set_pixel(x, y, to_pixel(color));
+                scope.spawn(move || {
+                    while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                        let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+                        for y in tile.y..tile.y + tile.height {
+                            for x in tile.x..tile.x + tile.width {
+                                let stats = film.pixel_stats(x, y);
+                                pixels.push(render_pixel(&mut sampler, x, y, stats));
+                            }
+                        }
+
+                        if sender.send((*tile, pixels)).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+
+            // Only the workers hold senders now, so this ends once they're all done
+            drop(sender);
+            receiver.into_iter().collect()
+        });
+
+        for (tile, pixels) in rendered {
+            for (i, stats) in pixels.into_iter().enumerate() {
+                let x = tile.x + i as u32 % tile.width;
+                let y = tile.y + i as u32 / tile.width;
+                film.set_pixel_stats(x, y, stats);
             }
         }
     }
 }
 
-fn ray_color(ray: Ray, scene: &Scene, rec_depth: i16) -> Vec3 {
+// Which pixels should keep taking samples. A pixel counts as noisy if any pixel next to it is, so
+// a rare bright path found in one pixel makes its neighbours look harder for it too
+fn noisy_pixels(film: &Film, noise_threshold: f32) -> Vec<bool> {
+    let (width, height) = (film.width(), film.height());
+    if noise_threshold <= 0.0 {
+        return vec![true; width as usize * height as usize];
+    }
+
+    let noise: Vec<f32> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| film.pixel_stats(x, y).noise())
+        .collect();
+
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (x0, x1) = (x.saturating_sub(1), u32::min(x + 1, width - 1));
+            let (y0, y1) = (y.saturating_sub(1), u32::min(y + 1, height - 1));
+            (y0..=y1)
+                .any(|ny| (x0..=x1).any(|nx| noise[(ny * width + nx) as usize] >= noise_threshold))
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+fn split_tiles(width: u32, height: u32, tile_size: u32) -> Vec<Tile> {
+    let tile_size = u32::max(tile_size, 1);
+    let mut tiles = Vec::new();
+
+    for y in (0..height).step_by(tile_size as usize) {
+        for x in (0..width).step_by(tile_size as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: u32::min(tile_size, width - x),
+                height: u32::min(tile_size, height - y),
+            });
+        }
+    }
+
+    tiles
+}
+
+fn ray_color(ray: Ray, scene: &Scene, rec_depth: i16, sampler: &mut impl Sampler) -> Vec3 {
     let hit_interval = Interval::new(0.001, f32::INFINITY);
 
     let mut scattered_ray = ray;
     let mut total_attenuation = Vec3::new(1., 1., 1.);
+    let mut color = Vec3::new(0., 0., 0.);
+
+    // Density the last bounce picked the ray's direction with, None for camera rays and
+    // specular bounces, which light sampling can't produce
+    let mut scatter_pdf: Option<f32> = None;
 
     let mut hit_info = HitInfo::default();
 
     for _ in 0..rec_depth {
-        if scene.hit(scattered_ray, hit_interval, &mut hit_info) {
-            let material = hit_info.material;
+        if !scene.hit(scattered_ray, hit_interval, &mut hit_info) {
+            return color + total_attenuation * scene.sky().color(scattered_ray.dir());
+        }
+
+        let material = hit_info.material;
+        let emitted = material.emission;
+
+        // Lights found by a bounce that could also have sampled them directly share the
+        // contribution with light sampling
+        let emission_weight = match scatter_pdf {
+            Some(scatter_pdf) if emitted.magnitude() > 0.0 => {
+                let distance = hit_info.t * scattered_ray.dir().magnitude();
+                let cos_light = scattered_ray.dir().dot(hit_info.normal).abs()
+                    / scattered_ray.dir().magnitude();
+                let light_pdf = scene.lights().pdf_area(emitted) * distance * distance / cos_light;
+
+                power_heuristic(scatter_pdf, light_pdf)
+            }
+            _ => 1.0,
+        };
+        color = color + total_attenuation * emitted * emission_weight;
 
-            let mut attenuation = Vec3::default();
+        // Every bounce takes the same dimensions whether it uses them or not, so a dimension
+        // always means the same thing to the sampler
+        let light_select = sampler.get_1d();
+        let light_u = sampler.get_2d();
+        let scatter_select = sampler.get_1d();
+        let scatter_u = sampler.get_2d();
+
+        if !material.is_specular() {
+            let light = scene.lights().sample(light_select, light_u[0], light_u[1]);
+            let direct = sample_light(scattered_ray, &hit_info, scene, light);
+            color = color + total_attenuation * direct;
+        }
+
+        let Some(scatter) = material.scatter(scattered_ray, &hit_info, scatter_select, scatter_u)
+        else {
+            return color;
+        };
+
+        scattered_ray = scatter.ray;
+        total_attenuation = total_attenuation * scatter.weight;
+        scatter_pdf = (!scatter.specular).then_some(scatter.pdf);
+    }
+
+    color
+}
 
-            let emitted = material.emission();
+// Light arriving directly from a sampled point on an emissive triangle, leaving the surface
+// back along `ray`, weighted against finding the same light by scattering
+fn sample_light(ray: Ray, hit_info: &HitInfo, scene: &Scene, light: Option<LightSample>) -> Vec3 {
+    let Some(light) = light else {
+        return Vec3::default();
+    };
 
-            if material.scatter(
-                scattered_ray,
-                &hit_info,
-                &mut attenuation,
-                &mut scattered_ray,
+    let to_light = light.point - hit_info.point;
+    let distance = to_light.magnitude();
+    let light_dir = to_light / distance;
+
+    let cos_light = light_dir.dot(light.normal).abs();
+    if cos_light <= 0.0 {
+        return Vec3::default();
+    }
+
+    let reflected = hit_info.material.eval(ray, hit_info, light_dir);
+    if reflected.magnitude() == 0.0 {
+        return Vec3::default();
+    }
+
+    // Anything between the surface and the light casts a shadow
+    let shadow_ray = Ray::new(hit_info.point, light_dir);
+    let shadow_interval = Interval::new(0.001, distance - 0.001);
+    if scene.hit(shadow_ray, shadow_interval, &mut HitInfo::default()) {
+        return Vec3::default();
+    }
+
+    // The density of the light point, converted from per area to per solid angle
+    let pdf = light.pdf_area * distance * distance / cos_light;
+    let scatter_pdf = hit_info.material.pdf(ray, hit_info, light_dir);
+
+    reflected * light.emission * (power_heuristic(pdf, scatter_pdf) / pdf)
+}
+
+// Weight for a sample taken with density `pdf` that another strategy could have taken with
+// density `other_pdf` (Veach 1997)
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (pdf2, other_pdf2) = (pdf * pdf, other_pdf * other_pdf);
+
+    if pdf2.is_infinite() {
+        return 1.0;
+    }
+
+    pdf2 / (pdf2 + other_pdf2)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::{
+        material::Material, quad::Quad, ray::Point3, rng::Pcg32, sphere::Sphere, triangle_mesh::Sky,
+    };
+
+    // Floor, glass ball, rough metal ball and a small light, in a few pixels
+    fn small_scene() -> (RayTracer, Scene) {
+        let mut scene = Scene::default();
+        scene.add(Quad::new(
+            Point3::new(-3.0, 0.0, 3.0),
+            Vec3::new(6.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -6.0),
+            Material::lambertian(Vec3::new(0.6, 0.6, 0.6)),
+        ));
+        scene.add(Sphere::new(
+            Point3::new(-0.6, 0.5, 0.0),
+            0.5,
+            Material::dielectric(1.5, Vec3::new(1.0, 1.0, 1.0)),
+        ));
+        scene.add(Sphere::new(
+            Point3::new(0.6, 0.5, 0.0),
+            0.5,
+            Material::metal(Vec3::new(0.8, 0.6, 0.4), 0.3),
+        ));
+        scene.add(Quad::new(
+            Point3::new(-0.3, 2.0, -0.3),
+            Vec3::new(0.0, 0.0, 0.6),
+            Vec3::new(0.6, 0.0, 0.0),
+            Material::emissive(Vec3::new(10.0, 10.0, 10.0)),
+        ));
+
+        let camera = Camera::new(
+            Point3::new(0.0, 1.0, 3.0),
+            Point3::new(0.0, 0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            50.0,
+        );
+        (RayTracer::new(camera), scene)
+    }
+
+    fn render(scene: &Scene, tracer: &RayTracer, settings: &RenderSettings) -> Film {
+        let mut film = Film::new(13, 9);
+        tracer.draw(&mut film, scene, settings, |_, _| {});
+        film
+    }
+
+    fn assert_same_film(a: &Film, b: &Film, what: &str) {
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+                assert_eq!(
+                    a.pixel_stats(x, y).count,
+                    b.pixel_stats(x, y).count,
+                    "{what}, pixel ({x}, {y})"
+                );
+                assert_eq!(
+                    [pa.x, pa.y, pa.z].map(f32::to_bits),
+                    [pb.x, pb.y, pb.z].map(f32::to_bits),
+                    "{what}, pixel ({x}, {y})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_renders_the_same_film_whatever_the_threads_and_tiles() {
+        let (tracer, scene) = small_scene();
+
+        let plain = RenderSettings {
+            samples: 6,
+            max_bounces: 5,
+            seed: 3,
+            ..RenderSettings::default()
+        };
+        let adaptive = RenderSettings {
+            samples: 16,
+            min_samples: 4,
+            noise_threshold: 0.05,
+            ..plain
+        };
+        let progressive = RenderSettings {
+            samples: 7,
+            pass_samples: 2,
+            ..plain
+        };
+
+        for sampler in [
+            SamplerKind::Independent,
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            for (name, settings) in [
+                ("plain", &plain),
+                ("adaptive", &adaptive),
+                ("progressive", &progressive),
+            ] {
+                let single = RenderSettings {
+                    sampler,
+                    threads: 1,
+                    tile_size: 32,
+                    ..*settings
+                };
+                let expected = render(&scene, &tracer, &single);
+
+                // Some pixels have to stop early, or adaptive sampling isn't being tested
+                if settings.noise_threshold > 0.0 {
+                    let stopped_early = (0..expected.height())
+                        .flat_map(|y| (0..expected.width()).map(move |x| (x, y)))
+                        .any(|(x, y)| expected.pixel_stats(x, y).count < settings.samples as u32);
+                    assert!(stopped_early);
+                }
+
+                for (threads, tile_size) in [(1, 1), (3, 4), (7, 2), (16, 5)] {
+                    let settings = RenderSettings {
+                        threads,
+                        tile_size,
+                        ..single
+                    };
+                    let film = render(&scene, &tracer, &settings);
+                    assert_same_film(
+                        &expected,
+                        &film,
+                        &format!("{name}, {threads} threads, {tile_size} pixel tiles"),
+                    );
+                }
+            }
+        }
+    }
+
+    const ALBEDO: f32 = 0.5;
+    const LIGHT_RADIANCE: f32 = 4.0;
+    const LIGHT_RADIUS: f32 = 0.25;
+    const LIGHT_HEIGHT: f32 = 1.0;
+
+    // A grey floor lit only by a small sphere floating above the origin
+    fn lit_floor() -> Scene {
+        let mut scene = Scene::default();
+        scene.set_sky(Sky {
+            horizon: Vec3::default(),
+            zenith: Vec3::default(),
+        });
+        scene.add(Quad::new(
+            Point3::new(-5.0, 0.0, 5.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -10.0),
+            Material::lambertian(Vec3::new(ALBEDO, ALBEDO, ALBEDO)),
+        ));
+        scene.add(Sphere::new(
+            Point3::new(0.0, LIGHT_HEIGHT, 0.0),
+            LIGHT_RADIUS,
+            Material::emissive(Vec3::new(LIGHT_RADIANCE, LIGHT_RADIANCE, LIGHT_RADIANCE)),
+        ));
+        scene
+    }
+
+    // The floor at the origin, seen at an angle that passes by the light
+    fn floor_hit(scene: &Scene) -> (Ray, HitInfo<'_>) {
+        let ray = Ray::new(Point3::new(1.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0));
+        let mut hit_info = HitInfo::default();
+        assert!(scene.hit(ray, Interval::new(0.001, f32::INFINITY), &mut hit_info));
+        assert!(hit_info.point.magnitude() < 1e-4);
+        (ray, hit_info)
+    }
+
+    // Density per solid angle of light sampling picking the point `light_hit` was found at, worked
+    // out from the hit like `ray_color` does
+    fn light_pdf_of_hit(scene: &Scene, ray: Ray, light_hit: &HitInfo) -> f32 {
+        let distance = light_hit.t * ray.dir().magnitude();
+        let cos_light = ray.dir().dot(light_hit.normal).abs() / ray.dir().magnitude();
+        scene.lights().pdf_area(light_hit.material.emission) * distance * distance / cos_light
+    }
+
+    #[test]
+    fn light_and_bsdf_sampling_agree_with_and_without_mis() {
+        let scene = lit_floor();
+        let (ray, hit_info) = floor_hit(&scene);
+        let interval = Interval::new(0.001, f32::INFINITY);
+        let mut rng = Pcg32::new(1, 0);
+        const N: usize = 200_000;
+
+        // A uniform sphere covering a solid angle straight above gives irradiance
+        // pi L (r / d)^2, of which a diffuse surface reflects albedo / pi
+        let exact = ALBEDO * LIGHT_RADIANCE * (LIGHT_RADIUS / LIGHT_HEIGHT).powi(2);
+
+        let mut bsdf_only = 0.0;
+        let mut light_only = 0.0;
+        for _ in 0..N {
+            let material = hit_info.material;
+            if let Some(scatter) =
+                material.scatter(ray, &hit_info, rng.random(), [rng.random(), rng.random()])
+            {
+                let mut light_hit = HitInfo::default();
+                if scene.hit(scatter.ray, interval, &mut light_hit) {
+                    bsdf_only += (scatter.weight * light_hit.material.emission).y;
+                }
+            }
+
+            let light = scene
+                .lights()
+                .sample(rng.random(), rng.random(), rng.random())
+                .unwrap();
+            let to_light = light.point - hit_info.point;
+            let distance = to_light.magnitude();
+            let light_dir = to_light / distance;
+            let shadow_interval = Interval::new(0.001, distance - 0.001);
+            if !scene.hit(
+                Ray::new(hit_info.point, light_dir),
+                shadow_interval,
+                &mut HitInfo::default(),
             ) {
-                total_attenuation = total_attenuation * attenuation;
-            } else {
-                return emitted * total_attenuation;
+                let pdf = light.pdf_area * distance * distance / light_dir.dot(light.normal).abs();
+                light_only += (material.eval(ray, &hit_info, light_dir) * light.emission).y / pdf;
             }
-        } else {
-            let dir_u = scattered_ray.dir() / scattered_ray.dir().magnitude();
-            let a = 0.5 * (dir_u.y + 1.0);
+        }
+
+        let mut sampler = IndependentSampler::new(7);
+        let mut combined = 0.0;
+        for i in 0..N as u32 {
+            sampler.start_sample(0, 0, i);
+            combined += ray_color(ray, &scene, 5, &mut sampler).y;
+        }
 
-            return total_attenuation
-                * ((1.0 - a) * Vec3::new(1.0, 1.0, 1.0) + a * Vec3::new(0.5, 0.7, 1.0));
+        for (name, sum) in [
+            ("bsdf sampling", bsdf_only),
+            ("light sampling", light_only),
+            ("mis", combined),
+        ] {
+            let estimate = sum / N as f32;
+            assert!(
+                (estimate - exact).abs() < 0.03 * exact,
+                "{name} gives {estimate}, expected {exact}"
+            );
         }
     }
 
-    return Vec3::new(0., 0., 0.);
+    #[test]
+    fn mis_weights_for_the_same_direction_sum_to_one() {
+        let scene = lit_floor();
+        let (ray, hit_info) = floor_hit(&scene);
+        let mut rng = Pcg32::new(2, 0);
+
+        let mut checked = 0;
+        for _ in 0..1000 {
+            let light = scene
+                .lights()
+                .sample(rng.random(), rng.random(), rng.random())
+                .unwrap();
+            let to_light = light.point - hit_info.point;
+            let distance = to_light.magnitude();
+            let light_dir = to_light / distance;
+
+            // The far side of the light can't be reached by a scattered ray, and at its edge the
+            // cosine is too small to come out the same when worked out twice
+            let cos_light = light_dir.dot(light.normal).abs();
+            let mut light_hit = HitInfo::default();
+            let toward_light = Ray::new(hit_info.point, light_dir);
+            scene.hit(
+                toward_light,
+                Interval::new(0.001, f32::INFINITY),
+                &mut light_hit,
+            );
+            if (light_hit.point - light.point).magnitude() > 1e-3 || cos_light < 0.05 {
+                continue;
+            }
+            checked += 1;
+
+            // The light's density as `sample_light` works it out, and as `ray_color` does when
+            // scattering finds the same point
+            let light_pdf = light.pdf_area * distance * distance / cos_light;
+            let light_pdf_from_hit = light_pdf_of_hit(&scene, toward_light, &light_hit);
+            assert!((light_pdf - light_pdf_from_hit).abs() < 1e-3 * light_pdf);
+
+            let scatter_pdf = hit_info.material.pdf(ray, &hit_info, light_dir);
+            assert!(scatter_pdf > 0.0);
+            let sum = power_heuristic(light_pdf, scatter_pdf)
+                + power_heuristic(scatter_pdf, light_pdf_from_hit);
+            assert!((sum - 1.0).abs() < 1e-4, "{sum}");
+        }
+        assert!(checked > 300);
+
+        // A strategy with infinite density takes the whole sample
+        assert_eq!(power_heuristic(f32::INFINITY, 2.0), 1.0);
+        assert_eq!(power_heuristic(2.0, f32::INFINITY), 0.0);
+    }
 }
@@ -0,0 +1,91 @@
+// PCG32 (O'Neill 2014): a 64 bit LCG with a permuted 32 bit output. Small, fast and with 2^63
+// selectable streams, so every sample of every pixel can get its own reproducible sequence.
+
+use rand::{RngCore, rand_core::impls};
+
+const MULTIPLIER: u64 = 6364136223846793005;
+
+#[derive(Clone)]
+pub struct Pcg32 {
+    state: u64,
+    // Odd, selects the stream
+    increment: u64,
+}
+
+impl Pcg32 {
+    // Same seeding as the reference implementation's pcg32_srandom_r
+    pub fn new(seed: u64, stream: u64) -> Pcg32 {
+        let mut rng = Pcg32 {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+        rng
+    }
+
+    fn step(&mut self) {
+        self.state = self
+            .state
+            .wrapping_mul(MULTIPLIER)
+            .wrapping_add(self.increment);
+    }
+}
+
+impl RngCore for Pcg32 {
+    fn next_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.step();
+
+        // XSH RR, xorshift the high bits down and rotate by the top 5 bits
+        let xor_shifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rotation = (old_state >> 59) as u32;
+        xor_shifted.rotate_right(rotation)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dst)
+    }
+}
+
+// Random stream for one sample of one pixel. Samples don't share a stream, so a sample's
+// randomness doesn't depend on how many samples came before it
+pub fn sample_rng(seed: u64, x: u32, y: u32, sample: u32) -> Pcg32 {
+    let pixel = pixel_hash(seed, x, y);
+    Pcg32::new(mix(pixel ^ sample as u64), pixel)
+}
+
+// Well scrambled 64 bits that only depend on the seed and the pixel
+pub fn pixel_hash(seed: u64, x: u32, y: u32) -> u64 {
+    mix(seed ^ ((y as u64) << 32 | x as u64).wrapping_mul(0x9e3779b97f4a7c15))
+}
+
+// Scrambles the bits of `z` (splitmix64 finalizer)
+pub fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_reference_implementation() {
+        // First outputs of pcg32-demo from the reference C implementation, seed 42, stream 54
+        let mut rng = Pcg32::new(42, 54);
+        let expected = [
+            0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e,
+        ];
+
+        for value in expected {
+            assert_eq!(rng.next_u32(), value);
+        }
+    }
+}
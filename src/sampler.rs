@@ -0,0 +1,427 @@
+// Where the random numbers of a path come from.
+//
+// A sampler hands out the values for one sample of one pixel, one or two dimensions at a time
+// (pixel offset, lens position, light choice, ...). Well spread samplers place the samples of a
+// pixel more evenly than independent random numbers would, which lowers noise at the same
+// sample count.
+
+use rand::Rng;
+
+use crate::rng::{Pcg32, mix, pixel_hash, sample_rng};
+
+pub trait Sampler {
+    // Starts sample `index` of pixel (x, y), dimensions are handed out from the first again
+    fn start_sample(&mut self, x: u32, y: u32, index: u32);
+
+    fn get_1d(&mut self) -> f32;
+
+    fn get_2d(&mut self) -> [f32; 2];
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    #[default]
+    Sobol,
+}
+
+// Every value is independent and uniformly random
+#[derive(Clone)]
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler {
+            seed,
+            rng: sample_rng(seed, 0, 0, 0),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.rng = sample_rng(self.seed, x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.random()
+    }
+
+    fn get_2d(&mut self) -> [f32; 2] {
+        [self.rng.random(), self.rng.random()]
+    }
+}
+
+// Splits every dimension into one stratum per sample and jitters within it. Samples visit the
+// strata in a different random order for each pixel and dimension, so dimensions don't correlate
+#[derive(Clone)]
+pub struct StratifiedSampler {
+    seed: u64,
+    samples: u32,
+    pixel: u64,
+    index: u32,
+    dimension: u32,
+    rng: Pcg32,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples: u32) -> StratifiedSampler {
+        StratifiedSampler {
+            seed,
+            samples: u32::max(samples, 1),
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+            rng: sample_rng(seed, 0, 0, 0),
+        }
+    }
+
+    // Stratum of the current sample among `count`. Past the expected sample count, each
+    // further round of samples goes through the strata in a new order
+    fn stratum(&mut self, count: u32) -> u32 {
+        let round = self.index / count;
+        let hash = mix(self.pixel ^ mix((self.dimension as u64) << 32 | round as u64));
+        self.dimension += 1;
+
+        permute(self.index % count, count, hash as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = pixel_hash(self.seed, x, y);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = sample_rng(self.seed, x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let stratum = self.stratum(self.samples);
+        (stratum as f32 + self.rng.random::<f32>()) / self.samples as f32
+    }
+
+    // A grid as close to square as the sample count allows, any cells left over stay empty
+    fn get_2d(&mut self) -> [f32; 2] {
+        let columns = f32::ceil(f32::sqrt(self.samples as f32)) as u32;
+        let rows = self.samples.div_ceil(columns);
+
+        let cell = self.stratum(columns * rows);
+        let (column, row) = (cell % columns, cell / columns);
+
+        [
+            (column as f32 + self.rng.random::<f32>()) / columns as f32,
+            (row as f32 + self.rng.random::<f32>()) / rows as f32,
+        ]
+    }
+}
+
+// The Halton sequence, with dimension d taking the radical inverse in the d-th prime base.
+// Each pixel and dimension shuffles the digits with its own random permutations (Owen
+// scrambling), without which the larger bases line up with each other
+#[derive(Clone)]
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u32,
+    rng: Pcg32,
+}
+
+// Larger bases take too many samples to cover their dimension well, deeper dimensions fall back
+// to independent random numbers
+const HALTON_BASES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+            rng: sample_rng(seed, 0, 0, 0),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = pixel_hash(self.seed, x, y);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = sample_rng(self.seed, x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        match HALTON_BASES.get(dimension as usize) {
+            Some(&base) => {
+                let hash = mix(self.pixel ^ dimension as u64);
+                scrambled_radical_inverse(base, self.index, hash)
+            }
+            None => self.rng.random(),
+        }
+    }
+
+    fn get_2d(&mut self) -> [f32; 2] {
+        [self.get_1d(), self.get_1d()]
+    }
+}
+
+// Owen scrambled Sobol points, padded from 2D (Burley 2020). Every pair of dimensions is the
+// first two Sobol dimensions, made independent of the other pairs by shuffling the sample order
+// differently for each. Owen scrambling keeps the points well spread within every pair
+#[derive(Clone)]
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    // Seeds for shuffling the sample order and scrambling both coordinates of the next
+    // dimension pair
+    fn next_seeds(&mut self) -> [u32; 3] {
+        let hash = mix(self.pixel ^ mix(self.dimension as u64));
+        self.dimension += 1;
+
+        let second = mix(hash);
+        [hash as u32, (hash >> 32) as u32, second as u32]
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = pixel_hash(self.seed, x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let [shuffle, scramble, _] = self.next_seeds();
+        let index = nested_uniform_scramble(self.index, shuffle);
+
+        to_unit_float(nested_uniform_scramble(index.reverse_bits(), scramble))
+    }
+
+    fn get_2d(&mut self) -> [f32; 2] {
+        let [shuffle, scramble_x, scramble_y] = self.next_seeds();
+        let index = nested_uniform_scramble(self.index, shuffle);
+
+        [
+            to_unit_float(nested_uniform_scramble(index.reverse_bits(), scramble_x)),
+            to_unit_float(nested_uniform_scramble(
+                sobol_second_dimension(index),
+                scramble_y,
+            )),
+        ]
+    }
+}
+
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+// The top 24 bits as a float in [0, 1)
+fn to_unit_float(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1 << 24) as f32
+}
+
+// Mirrors the base `base` digits of `index` around the radix point, permuting every digit
+// depending on the digits before it. Zero digits past the end of `index` get permuted too, until
+// they're too small to matter
+fn scrambled_radical_inverse(base: u32, mut index: u32, hash: u64) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let mut reversed: u64 = 0;
+    let mut scale = 1.0;
+
+    while scale > 1e-8 {
+        let digit = index % base;
+        index /= base;
+
+        let digit_hash = mix(hash ^ reversed) as u32;
+        reversed = reversed * base as u64 + permute(digit, base, digit_hash) as u64;
+        scale *= inverse_base;
+    }
+
+    f32::min((reversed as f64 * scale) as f32, ONE_MINUS_EPSILON)
+}
+
+// The second dimension of the Sobol sequence, the first is just the bits of the index reversed
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1 << 31;
+
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+
+    result
+}
+
+// Owen scrambling of the bits of `x`, read from the most significant one, as a hash
+// (Laine and Karras 2011, with the constants from Burley 2020)
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+
+    x.reverse_bits()
+}
+
+// Position of `i` in a random permutation of 0..count chosen by `seed`, without building the
+// permutation (Kensler 2013)
+fn permute(mut i: u32, count: u32, seed: u32) -> u32 {
+    let mut mask = count - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    // Cycle walk, a bijection on the next power of two, repeated until it lands below count
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= mask;
+        i ^= i >> 5;
+
+        if i < count {
+            return (i.wrapping_add(seed)) % count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Index of the cell each point lands in, for a grid of columns x rows over the unit square
+    fn cells(points: &[[f32; 2]], columns: u32, rows: u32) -> Vec<u32> {
+        let mut cells: Vec<u32> = points
+            .iter()
+            .map(|&[x, y]| (y * rows as f32) as u32 * columns + (x * columns as f32) as u32)
+            .collect();
+        cells.sort();
+        cells
+    }
+
+    // The first samples of a pixel in one dimension pair, after skipping `dimension` pairs
+    fn points(sampler: &mut impl Sampler, count: u32, dimension: u32) -> Vec<[f32; 2]> {
+        (0..count)
+            .map(|index| {
+                sampler.start_sample(3, 5, index);
+                for _ in 0..dimension {
+                    sampler.get_2d();
+                }
+                sampler.get_2d()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn permute_is_a_permutation() {
+        for count in [1, 2, 7, 16, 100] {
+            for seed in [0, 1, 0xdeadbeef] {
+                let mut values: Vec<u32> = (0..count).map(|i| permute(i, count, seed)).collect();
+                values.sort();
+                assert_eq!(values, (0..count).collect::<Vec<u32>>());
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_pairs_are_nets() {
+        // Every power of two of samples puts one point in each cell of every grid with that
+        // many cells, in each dimension pair
+        let expected: Vec<u32> = (0..16).collect();
+        for dimension in 0..4 {
+            let points = points(&mut SobolSampler::new(1), 16, dimension);
+            for (columns, rows) in [(1, 16), (2, 8), (4, 4), (8, 2), (16, 1)] {
+                assert_eq!(cells(&points, columns, rows), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_covers_every_stratum() {
+        let expected: Vec<u32> = (0..16).collect();
+        for dimension in 0..4 {
+            let points = points(&mut StratifiedSampler::new(1, 16), 16, dimension);
+            assert_eq!(cells(&points, 4, 4), expected);
+        }
+    }
+
+    #[test]
+    fn halton_stratifies_each_dimension() {
+        // The first base^2 samples put one point in each of base^2 equal intervals
+        let mut sampler = HaltonSampler::new(1);
+        for (dimension, base) in HALTON_BASES.iter().take(6).enumerate() {
+            let count = base * base;
+            let mut intervals: Vec<u32> = (0..count)
+                .map(|index| {
+                    sampler.start_sample(3, 5, index);
+                    for _ in 0..dimension {
+                        sampler.get_1d();
+                    }
+                    (sampler.get_1d() * count as f32) as u32
+                })
+                .collect();
+            intervals.sort();
+            assert_eq!(intervals, (0..count).collect::<Vec<u32>>());
+        }
+    }
+
+    #[test]
+    fn samples_are_reproducible() {
+        let mut first = SobolSampler::new(7);
+        let mut second = first.clone();
+
+        first.start_sample(10, 20, 5);
+        let expected = [first.get_1d(), first.get_1d()];
+
+        second.start_sample(0, 0, 0);
+        second.get_2d();
+        second.start_sample(10, 20, 5);
+        assert_eq!([second.get_1d(), second.get_1d()], expected);
+    }
+}
@@ -0,0 +1,158 @@
+// Warps uniform points in the unit square onto the shapes we sample directions and points from.
+// Each mapping is continuous, so well spread input points stay well spread.
+
+use core::f32;
+
+use crate::vec3::Vec3;
+
+// Shirley's concentric mapping onto the unit disk
+pub fn concentric_disk(u: [f32; 2]) -> (f32, f32) {
+    let a = 2.0 * u[0] - 1.0;
+    let b = 2.0 * u[1] - 1.0;
+
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (radius, theta) = if a.abs() > b.abs() {
+        (a, f32::consts::FRAC_PI_4 * (b / a))
+    } else {
+        (b, f32::consts::FRAC_PI_2 - f32::consts::FRAC_PI_4 * (a / b))
+    };
+
+    (radius * theta.cos(), radius * theta.sin())
+}
+
+// Direction in the +z hemisphere with density cos(theta) / pi, by lifting a uniform point on
+// the disk up onto the hemisphere (Malley's method)
+pub fn cosine_hemisphere(u: [f32; 2]) -> Vec3 {
+    let (x, y) = concentric_disk(u);
+    let z = f32::sqrt(f32::max(0.0, 1.0 - x * x - y * y));
+
+    Vec3::new(x, y, z)
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: f32) -> f32 {
+    f32::max(0.0, cos_theta) / f32::consts::PI
+}
+
+// Direction with the same density everywhere on the unit sphere, 1 / (4 pi)
+pub fn uniform_sphere(u: [f32; 2]) -> Vec3 {
+    // By Archimedes' hat-box theorem, z is uniform for a uniform point on the sphere
+    let z = 1.0 - 2.0 * u[0];
+    let r = f32::sqrt(f32::max(0.0, 1.0 - z * z));
+    let phi = 2.0 * f32::consts::PI * u[1];
+
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    const SAMPLES: usize = 200_000;
+    const BINS: usize = 10;
+
+    // Pearson's chi-squared statistic over BINS x BINS equally likely cells
+    fn chi_squared(cells: impl Iterator<Item = (f32, f32)>) -> f32 {
+        let mut counts = [[0usize; BINS]; BINS];
+        for (a, b) in cells {
+            let i = usize::min((a * BINS as f32) as usize, BINS - 1);
+            let j = usize::min((b * BINS as f32) as usize, BINS - 1);
+            counts[i][j] += 1;
+        }
+
+        let expected = SAMPLES as f32 / (BINS * BINS) as f32;
+        counts
+            .iter()
+            .flatten()
+            .map(|&count| (count as f32 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    // 99 degrees of freedom, a correct sampler only exceeds this one time in a thousand
+    const CHI_SQUARED_CRITICAL: f32 = 148.2;
+
+    fn square_points() -> impl Iterator<Item = [f32; 2]> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..SAMPLES).map(move |_| [rng.random(), rng.random()])
+    }
+
+    // Azimuth of a direction as a fraction of a full turn
+    fn azimuth(w: Vec3) -> f32 {
+        (f32::atan2(w.y, w.x) / (2.0 * f32::consts::PI)).rem_euclid(1.0)
+    }
+
+    #[test]
+    fn uniform_sphere_is_uniform() {
+        for w in square_points().map(uniform_sphere) {
+            assert!((w.magnitude() - 1.0).abs() < 1e-5);
+        }
+
+        // Equal bands of z cut the sphere into equal areas
+        let stat = chi_squared(
+            square_points()
+                .map(uniform_sphere)
+                .map(|w| ((w.z + 1.0) / 2.0, azimuth(w))),
+        );
+        assert!(stat < CHI_SQUARED_CRITICAL, "chi squared {stat}");
+    }
+
+    #[test]
+    fn cosine_hemisphere_is_cosine_weighted() {
+        for w in square_points().map(cosine_hemisphere) {
+            assert!((w.magnitude() - 1.0).abs() < 1e-5);
+            assert!(w.z >= 0.0);
+        }
+
+        // With density cos(theta) / pi, cos^2(theta) is uniform in [0, 1]
+        let stat = chi_squared(
+            square_points()
+                .map(cosine_hemisphere)
+                .map(|w| (w.z * w.z, azimuth(w))),
+        );
+        assert!(stat < CHI_SQUARED_CRITICAL, "chi squared {stat}");
+    }
+
+    #[test]
+    fn cosine_hemisphere_pdf_integrates_to_one() {
+        // Integrates the pdf over the sphere with uniformly spread directions
+        let estimate: f32 = square_points()
+            .map(uniform_sphere)
+            .map(|w| cosine_hemisphere_pdf(w.z) * 4.0 * f32::consts::PI)
+            .sum::<f32>()
+            / SAMPLES as f32;
+        assert!((estimate - 1.0).abs() < 0.01, "integral {estimate}");
+    }
+
+    #[test]
+    fn cosine_hemisphere_matches_its_pdf() {
+        // Monte Carlo estimate of the integral of cos^2 over the hemisphere, 2 pi / 3, which is
+        // only right if the samples really have the density the pdf claims
+        let estimate: f32 = square_points()
+            .map(cosine_hemisphere)
+            .filter(|w| w.z > 0.0)
+            .map(|w| w.z * w.z / cosine_hemisphere_pdf(w.z))
+            .sum::<f32>()
+            / SAMPLES as f32;
+        let expected = 2.0 * f32::consts::PI / 3.0;
+        assert!((estimate - expected).abs() < 0.01, "integral {estimate}");
+    }
+
+    #[test]
+    fn concentric_disk_is_uniform() {
+        let points: Vec<(f32, f32)> = square_points().map(concentric_disk).collect();
+        for &(x, y) in &points {
+            assert!(x * x + y * y <= 1.0 + 1e-5);
+        }
+
+        // Equal steps of r^2 cut the disk into equal areas
+        let stat = chi_squared(points.iter().map(|&(x, y)| {
+            let w = Vec3::new(x, y, 0.0);
+            (x * x + y * y, azimuth(w))
+        }));
+        assert!(stat < CHI_SQUARED_CRITICAL, "chi squared {stat}");
+    }
+}
@@ -0,0 +1,807 @@
+// Plain text scene description, see the "Scene files" section of the README for the format.
+//
+// Every line is a keyword followed by whitespace separated values, `#` starts a comment.
+// `camera`, `mesh`, `light` and `instance` open a block that is closed by `end`.
+// The line tokenizer is shared with the other text formats (OBJ, MTL).
+
+use std::{collections::HashMap, error::Error, fmt, fs, io, path::Path, sync::Arc, time::Duration};
+
+use crate::{
+    camera::Camera,
+    canvas::ToneMapOperator,
+    image_output::ImageFormat,
+    instance::Instance,
+    material::Material,
+    matrix::Mat3,
+    obj::load_obj,
+    quad::Quad,
+    quat::Quat,
+    raytracer::RenderSettings,
+    sampler::SamplerKind,
+    sphere::Sphere,
+    transform::Transform,
+    triangle_mesh::{Scene, Sky, TriangleMesh},
+    vec3::Vec3,
+};
+
+pub struct SceneDescription {
+    pub scene: Scene,
+    pub camera: Camera,
+    pub settings: RenderSettings,
+    pub width: u32,
+    pub height: u32,
+    pub output: String,
+    // Where to save the number of samples each pixel took, if anywhere
+    pub sample_map: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+pub fn load_scene(filename: &str) -> Result<SceneDescription, io::Error> {
+    let text = fs::read_to_string(filename)?;
+    let dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+
+    parse_scene(&text, dir)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{filename}:{err}")))
+}
+
+enum Block {
+    Top,
+    Camera,
+    Mesh(Box<MeshBlock>),
+    Instance(Box<InstanceBlock>),
+}
+
+// A mesh, moved into place by the transforms in the block once it's complete
+struct MeshBlock {
+    mesh: TriangleMesh,
+    transform: Transform,
+}
+
+// Copies of every mesh from an OBJ file, placed by the transforms in the block
+struct InstanceBlock {
+    meshes: Vec<Arc<TriangleMesh>>,
+    material: Option<Material>,
+    transform: Transform,
+}
+
+// Files referenced by the scene are relative to `dir`
+pub fn parse_scene(text: &str, dir: &Path) -> Result<SceneDescription, ParseError> {
+    let mut description = SceneDescription {
+        scene: Scene::default(),
+        camera: Camera::default(),
+        settings: RenderSettings::default(),
+        width: 1280,
+        height: 720,
+        output: String::from("examples/test.bmp"),
+        sample_map: None,
+    };
+
+    let mut materials: HashMap<&str, Material> = HashMap::new();
+    // Every instance of an OBJ file shares the meshes loaded the first time, per smoothing angle
+    let mut instanced_objs: HashMap<(&str, u32), Vec<Arc<TriangleMesh>>> = HashMap::new();
+    let mut block = Block::Top;
+    // Line and column of the keyword that opened the current block
+    let mut block_start = (0, 0);
+    // The camera focuses on its look at point unless told otherwise
+    let mut focus_distance: Option<f32> = None;
+    // Meshes without vertex normals defined from here on get smoothed up to this angle
+    let mut smooth_angle = 0.0;
+
+    for (line_idx, line_text) in text.lines().enumerate() {
+        let mut line = Line::new(line_idx + 1, line_text);
+
+        let Some(keyword) = line.keyword() else {
+            continue;
+        };
+
+        block = match (block, keyword.text) {
+            (Block::Top, "image") => {
+                description.width = line.next_u32("image width")?;
+                description.height = line.next_u32("image height")?;
+                if description.width == 0 || description.height == 0 {
+                    return Err(keyword.error("image size must not be zero"));
+                }
+                Block::Top
+            }
+            (Block::Top, "samples") => {
+                let token = line.peek_token("sample count")?;
+                description.settings.samples = i32::try_from(line.next_u32("sample count")?)
+                    .map_err(|_| {
+                        token.error(&format!("sample count must be at most {}", i32::MAX))
+                    })?;
+                if description.settings.samples == 0 {
+                    return Err(token.error("sample count must not be zero"));
+                }
+                Block::Top
+            }
+            (Block::Top, "min_samples") => {
+                let token = line.peek_token("sample count")?;
+                description.settings.min_samples = i32::try_from(line.next_u32("sample count")?)
+                    .map_err(|_| {
+                        token.error(&format!("sample count must be at most {}", i32::MAX))
+                    })?;
+                Block::Top
+            }
+            (Block::Top, "noise_threshold") => {
+                description.settings.noise_threshold = line.next_f32("noise threshold")?;
+                Block::Top
+            }
+            (Block::Top, "pass_samples") => {
+                let token = line.peek_token("sample count")?;
+                description.settings.pass_samples = i32::try_from(line.next_u32("sample count")?)
+                    .map_err(|_| {
+                    token.error(&format!("sample count must be at most {}", i32::MAX))
+                })?;
+                Block::Top
+            }
+            (Block::Top, "snapshot_interval") => {
+                let token = line.peek_token("snapshot interval")?;
+                let seconds = line.next_f32("snapshot interval")?;
+                if seconds < 0.0 {
+                    return Err(token.error("snapshot interval must not be negative"));
+                }
+                description.settings.snapshot_interval = Duration::from_secs_f32(seconds);
+                Block::Top
+            }
+            (Block::Top, "bounces") => {
+                let token = line.peek_token("bounce count")?;
+                description.settings.max_bounces = i16::try_from(line.next_u32("bounce count")?)
+                    .map_err(|_| {
+                        token.error(&format!("bounce count must be at most {}", i16::MAX))
+                    })?;
+                Block::Top
+            }
+            (Block::Top, "threads") => {
+                description.settings.threads = line.next_u32("thread count")? as usize;
+                Block::Top
+            }
+            (Block::Top, "tile_size") => {
+                description.settings.tile_size = line.next_u32("tile size")?;
+                Block::Top
+            }
+            (Block::Top, "seed") => {
+                description.settings.seed = line.next_u64("seed")?;
+                Block::Top
+            }
+            (Block::Top, "sampler") => {
+                let sampler = line.next_token("sampler")?;
+                description.settings.sampler = match sampler.text {
+                    "independent" => SamplerKind::Independent,
+                    "stratified" => SamplerKind::Stratified,
+                    "halton" => SamplerKind::Halton,
+                    "sobol" => SamplerKind::Sobol,
+                    other => {
+                        let message = format!("unknown sampler '{other}'");
+                        return Err(sampler.error(&message));
+                    }
+                };
+                Block::Top
+            }
+            (Block::Top, "tone_map") => {
+                let operator = line.next_token("tone mapping operator")?;
+                description.settings.tone_mapping.operator = match operator.text {
+                    "clamp" => ToneMapOperator::Clamp,
+                    "reinhard" => ToneMapOperator::Reinhard,
+                    "aces" => ToneMapOperator::Aces,
+                    other => {
+                        let message = format!("unknown tone mapping operator '{other}'");
+                        return Err(operator.error(&message));
+                    }
+                };
+                Block::Top
+            }
+            (Block::Top, "exposure") => {
+                description.settings.tone_mapping.exposure = line.next_f32("exposure")?;
+                Block::Top
+            }
+            (Block::Top, "output") => {
+                let output = line.next_token("output file name")?;
+                if ImageFormat::from_filename(output.text).is_none() {
+                    return Err(
+                        output.error("output must be a .bmp, .png, .pfm, .hdr or .exr file")
+                    );
+                }
+                description.output = output.text.to_string();
+                Block::Top
+            }
+            (Block::Top, "sample_map") => {
+                let sample_map = line.next_token("sample map file name")?;
+                if ImageFormat::from_filename(sample_map.text).is_none() {
+                    return Err(sample_map
+                        .error("sample map must be a .bmp, .png, .pfm, .hdr or .exr file"));
+                }
+                description.sample_map = Some(sample_map.text.to_string());
+                Block::Top
+            }
+            (Block::Top, "smooth_angle") => {
+                let token = line.peek_token("smoothing angle")?;
+                smooth_angle = line.next_f32("smoothing angle")?;
+                if !(0.0..=180.0).contains(&smooth_angle) {
+                    return Err(token.error("smoothing angle must be between 0 and 180 degrees"));
+                }
+                Block::Top
+            }
+            (Block::Top, "sky") => {
+                let horizon = line.next_vec3("sky horizon color")?;
+                let zenith = line.next_vec3("sky zenith color")?;
+                description.scene.set_sky(Sky { horizon, zenith });
+                Block::Top
+            }
+            (Block::Top, "material") => {
+                let name = line.next_token("material name")?;
+                let kind = line.next_token("material type")?;
+                if !matches!(
+                    kind.text,
+                    "lambertian" | "metal" | "dielectric" | "emissive"
+                ) {
+                    let message = format!("unknown material type '{}'", kind.text);
+                    return Err(kind.error(&message));
+                }
+
+                let albedo = line.next_vec3("material color")?;
+                let material = match kind.text {
+                    "lambertian" => Material::lambertian(albedo),
+                    "metal" => {
+                        let mut roughness = 0.0;
+                        if let Some(token) = line.next_optional() {
+                            roughness = token.parse_f32("roughness")?;
+                            if !(0.0..=1.0).contains(&roughness) {
+                                return Err(token.error("roughness must be between 0 and 1"));
+                            }
+                        }
+                        Material::metal(albedo, roughness)
+                    }
+                    "dielectric" => {
+                        let ior_token = line.peek_token("index of refraction")?;
+                        let ior = line.next_f32("index of refraction")?;
+                        if ior <= 0.0 {
+                            return Err(ior_token.error("index of refraction must be positive"));
+                        }
+                        Material::dielectric(ior, albedo)
+                    }
+                    _ => Material::emissive(albedo),
+                };
+
+                materials.insert(name.text, material);
+                Block::Top
+            }
+            (Block::Top, "obj") => {
+                let file = line.next_token("OBJ file name")?;
+                let material = match line.next_optional() {
+                    Some(name) => match materials.get(name.text) {
+                        Some(material) => Some(material.clone()),
+                        None => {
+                            return Err(name.error(&format!("unknown material '{}'", name.text)));
+                        }
+                    },
+                    None => None,
+                };
+
+                let meshes = load_obj(&dir.join(file.text).to_string_lossy())
+                    .map_err(|err| file.error(&format!("failed to load OBJ: {err}")))?;
+
+                for mut mesh in meshes {
+                    if let Some(material) = &material {
+                        mesh.set_material(material.clone());
+                    }
+                    smooth(&mut mesh, smooth_angle);
+                    description.scene.add_mesh(mesh);
+                }
+                Block::Top
+            }
+            (Block::Top, "sphere") => {
+                let center = line.next_vec3("sphere center")?;
+                let radius_token = line.peek_token("sphere radius")?;
+                let radius = line.next_f32("sphere radius")?;
+                if radius <= 0.0 {
+                    return Err(radius_token.error("sphere radius must be positive"));
+                }
+
+                let name = line.next_token("material name")?;
+                let Some(material) = materials.get(name.text) else {
+                    return Err(name.error(&format!("unknown material '{}'", name.text)));
+                };
+                description
+                    .scene
+                    .add(Sphere::new(center, radius, material.clone()));
+                Block::Top
+            }
+            (Block::Top, "quad") => {
+                let corner = line.next_vec3("quad corner")?;
+                let u_token = line.peek_token("quad edge")?;
+                let u = line.next_vec3("quad edge")?;
+                let v = line.next_vec3("quad edge")?;
+                if u.cross(v).magnitude() == 0.0 {
+                    return Err(u_token.error("quad edges must not be parallel"));
+                }
+
+                let name = line.next_token("material name")?;
+                let Some(material) = materials.get(name.text) else {
+                    return Err(name.error(&format!("unknown material '{}'", name.text)));
+                };
+                description
+                    .scene
+                    .add(Quad::new(corner, u, v, material.clone()));
+                Block::Top
+            }
+            (Block::Top, "instance") => {
+                let file = line.next_token("OBJ file name")?;
+                let material = match line.next_optional() {
+                    Some(name) => match materials.get(name.text) {
+                        Some(material) => Some(material.clone()),
+                        None => {
+                            return Err(name.error(&format!("unknown material '{}'", name.text)));
+                        }
+                    },
+                    None => None,
+                };
+
+                let key = (file.text, smooth_angle.to_bits());
+                let meshes = match instanced_objs.get(&key) {
+                    Some(meshes) => meshes.clone(),
+                    None => {
+                        let meshes: Vec<Arc<TriangleMesh>> =
+                            load_obj(&dir.join(file.text).to_string_lossy())
+                                .map_err(|err| file.error(&format!("failed to load OBJ: {err}")))?
+                                .into_iter()
+                                .map(|mut mesh| {
+                                    smooth(&mut mesh, smooth_angle);
+                                    Arc::new(mesh)
+                                })
+                                .collect();
+                        instanced_objs.insert(key, meshes.clone());
+                        meshes
+                    }
+                };
+
+                block_start = (keyword.line, keyword.column);
+                Block::Instance(Box::new(InstanceBlock {
+                    meshes,
+                    material,
+                    transform: Transform::default(),
+                }))
+            }
+            (Block::Top, "camera") => {
+                block_start = (keyword.line, keyword.column);
+                Block::Camera
+            }
+            (Block::Top, "mesh") => {
+                let name = line.next_token("material name")?;
+                let Some(material) = materials.get(name.text) else {
+                    return Err(name.error(&format!("unknown material '{}'", name.text)));
+                };
+                block_start = (keyword.line, keyword.column);
+                Block::Mesh(Box::new(MeshBlock {
+                    mesh: TriangleMesh::new(material.clone()),
+                    transform: Transform::default(),
+                }))
+            }
+            (Block::Top, "light") => {
+                let emission = line.next_vec3("light color")?;
+                block_start = (keyword.line, keyword.column);
+                Block::Mesh(Box::new(MeshBlock {
+                    mesh: TriangleMesh::new(Material::emissive(emission)),
+                    transform: Transform::default(),
+                }))
+            }
+            (Block::Camera, "position") => {
+                description.camera.position = line.next_vec3("camera position")?;
+                Block::Camera
+            }
+            (Block::Camera, "look_at") => {
+                description.camera.look_at = line.next_vec3("camera look at point")?;
+                Block::Camera
+            }
+            (Block::Camera, "up") => {
+                description.camera.up = line.next_vec3("camera up vector")?;
+                Block::Camera
+            }
+            (Block::Camera, "fov") => {
+                let token = line.peek_token("field of view")?;
+                let vfov = line.next_f32("field of view")?;
+                if !(vfov > 0.0 && vfov < 180.0) {
+                    return Err(token.error("field of view must be between 0 and 180 degrees"));
+                }
+                description.camera.vfov = vfov;
+                Block::Camera
+            }
+            (Block::Camera, "aperture") => {
+                let token = line.peek_token("aperture radius")?;
+                let aperture = line.next_f32("aperture radius")?;
+                if !(aperture.is_finite() && aperture >= 0.0) {
+                    return Err(token.error("aperture radius must not be negative"));
+                }
+                description.camera.aperture = aperture;
+                Block::Camera
+            }
+            (Block::Camera, "focus_distance") => {
+                let token = line.peek_token("focus distance")?;
+                let distance = line.next_f32("focus distance")?;
+                if !(distance.is_finite() && distance > 0.0) {
+                    return Err(token.error("focus distance must be positive"));
+                }
+                focus_distance = Some(distance);
+                Block::Camera
+            }
+            (Block::Mesh(mut block), "vertex") => {
+                block.mesh.add_vertex(line.next_vec3("vertex position")?);
+                Block::Mesh(block)
+            }
+            (Block::Mesh(mut block), "triangle") => {
+                let mesh = &mut block.mesh;
+                let mut vertex_indices = [0; 3];
+                for vertex_index in &mut vertex_indices {
+                    let token = line.peek_token("vertex index")?;
+                    *vertex_index = line.next_u32("vertex index")?;
+                    if *vertex_index as usize >= mesh.vertex_count() {
+                        return Err(token.error(&format!(
+                            "vertex index {} out of range, mesh has {} vertices",
+                            vertex_index,
+                            mesh.vertex_count()
+                        )));
+                    }
+                }
+                mesh.add_triangle(vertex_indices[0], vertex_indices[1], vertex_indices[2]);
+                Block::Mesh(block)
+            }
+            (
+                Block::Mesh(mut block),
+                "translate" | "rotate" | "scale" | "orient" | "look_at" | "matrix",
+            ) => {
+                block.transform = block.transform.then(parse_transform(keyword, &mut line)?);
+                Block::Mesh(block)
+            }
+            (Block::Mesh(block), "end") => {
+                let MeshBlock {
+                    mut mesh,
+                    transform,
+                } = *block;
+                mesh.transform(&transform);
+                smooth(&mut mesh, smooth_angle);
+                description.scene.add_mesh(mesh);
+                Block::Top
+            }
+            (
+                Block::Instance(mut instance),
+                "translate" | "rotate" | "scale" | "orient" | "look_at" | "matrix",
+            ) => {
+                instance.transform = instance
+                    .transform
+                    .then(parse_transform(keyword, &mut line)?);
+                Block::Instance(instance)
+            }
+            (Block::Instance(instance), "end") => {
+                for mesh in &instance.meshes {
+                    description.scene.add(Instance::new(
+                        mesh.clone(),
+                        instance.transform,
+                        instance.material.clone(),
+                    ));
+                }
+                Block::Top
+            }
+            (Block::Camera, "end") => {
+                // Nothing to look along, the camera has no orientation
+                let camera = &description.camera;
+                if (camera.look_at - camera.position).magnitude() == 0.0 {
+                    return Err(ParseError {
+                        line: block_start.0,
+                        column: block_start.1,
+                        message: String::from("camera look at point must differ from its position"),
+                    });
+                }
+                Block::Top
+            }
+            (Block::Top, "end") => return Err(keyword.error("'end' without an open block")),
+            (_, other) => return Err(keyword.error(&format!("unexpected '{other}'"))),
+        };
+
+        line.finish()?;
+    }
+
+    if !matches!(block, Block::Top) {
+        return Err(ParseError {
+            line: block_start.0,
+            column: block_start.1,
+            message: String::from("block is never closed with 'end'"),
+        });
+    }
+
+    description.camera.focus_distance = focus_distance
+        .unwrap_or_else(|| (description.camera.look_at - description.camera.position).magnitude());
+
+    Ok(description)
+}
+
+// Smooths meshes that came without their own normals, an angle of 0 keeps them faceted
+fn smooth(mesh: &mut TriangleMesh, smooth_angle: f32) {
+    if smooth_angle > 0.0 && !mesh.has_vertex_normals() {
+        mesh.smooth_normals(smooth_angle);
+    }
+}
+
+// One of the statements that move the contents of a `mesh`, `light` or `instance` block
+fn parse_transform(keyword: Token, line: &mut Line) -> Result<Transform, ParseError> {
+    match keyword.text {
+        "translate" => Ok(Transform::translate(line.next_vec3("translation")?)),
+        "rotate" => {
+            let axis_token = line.peek_token("rotation axis")?;
+            let axis = line.next_vec3("rotation axis")?;
+            if axis.magnitude() == 0.0 {
+                return Err(axis_token.error("rotation axis must not be zero"));
+            }
+            let degrees = line.next_f32("rotation angle")?;
+            Ok(Transform::rotate(axis, degrees))
+        }
+        "scale" => {
+            let factors_token = line.peek_token("scale factors")?;
+            let factors = line.next_vec3("scale factors")?;
+            if factors.x == 0.0 || factors.y == 0.0 || factors.z == 0.0 {
+                return Err(factors_token.error("scale factors must not be zero"));
+            }
+            Ok(Transform::scale(factors))
+        }
+        "orient" => {
+            let token = line.peek_token("quaternion")?;
+            let quat = Quat {
+                x: line.next_f32("quaternion x")?,
+                y: line.next_f32("quaternion y")?,
+                z: line.next_f32("quaternion z")?,
+                w: line.next_f32("quaternion w")?,
+            };
+            if quat.dot(quat) == 0.0 {
+                return Err(token.error("quaternion must not be zero"));
+            }
+            Ok(Transform::from_quat(quat))
+        }
+        "look_at" => {
+            let token = line.peek_token("position")?;
+            let position = line.next_vec3("position")?;
+            let target = line.next_vec3("look at point")?;
+            let up = line.next_vec3("up vector")?;
+            Transform::look_at(position, target, up).ok_or_else(|| {
+                token.error("look at point must differ from the position and not lie along up")
+            })
+        }
+        "matrix" => {
+            let token = line.peek_token("matrix")?;
+            let mut rows = [[0.0; 4]; 3];
+            for row in &mut rows {
+                for value in row.iter_mut() {
+                    *value = line.next_f32("matrix element")?;
+                }
+            }
+
+            let linear = Mat3 {
+                rows: rows.map(|row| [row[0], row[1], row[2]]),
+            };
+            let translation = Vec3::new(rows[0][3], rows[1][3], rows[2][3]);
+            Transform::from_affine(linear, translation)
+                .ok_or_else(|| token.error("matrix must be invertible"))
+        }
+        other => Err(keyword.error(&format!("unexpected '{other}'"))),
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Token<'_> {
+    pub fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn parse_f32(&self, what: &str) -> Result<f32, ParseError> {
+        match self.text.parse::<f32>() {
+            Ok(val) if val.is_finite() => Ok(val),
+            _ => Err(self.error(&format!("expected {what}, found '{}'", self.text))),
+        }
+    }
+}
+
+pub struct Line<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    line: usize,
+    // Column just past the last token, where missing values are reported
+    end_column: usize,
+}
+
+impl<'a> Line<'a> {
+    pub fn new(line: usize, text: &'a str) -> Line<'a> {
+        let text = match text.find('#') {
+            Some(comment) => &text[..comment],
+            None => text,
+        };
+
+        let mut tokens = Vec::new();
+        let mut start: Option<usize> = None;
+
+        for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+            match (start, c.is_whitespace()) {
+                (None, false) => start = Some(i),
+                (Some(s), true) => {
+                    tokens.push(Token {
+                        text: &text[s..i],
+                        line,
+                        column: text[..s].chars().count() + 1,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        Line {
+            tokens,
+            pos: 0,
+            line,
+            end_column: text.trim_end().chars().count() + 1,
+        }
+    }
+
+    // First token of the line, None for empty and comment only lines
+    pub fn keyword(&mut self) -> Option<Token<'a>> {
+        self.pos = 1;
+        self.tokens.first().copied()
+    }
+
+    // Next token if there is one, for values that may be left out
+    pub fn next_optional(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += token.is_some() as usize;
+        token
+    }
+
+    pub fn peek_token(&self, what: &str) -> Result<Token<'a>, ParseError> {
+        self.tokens
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| ParseError {
+                line: self.line,
+                column: self.end_column,
+                message: format!("expected {what}"),
+            })
+    }
+
+    pub fn next_token(&mut self, what: &str) -> Result<Token<'a>, ParseError> {
+        let token = self.peek_token(what)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    pub fn next_f32(&mut self, what: &str) -> Result<f32, ParseError> {
+        self.next_token(what)?.parse_f32(what)
+    }
+
+    pub fn next_u32(&mut self, what: &str) -> Result<u32, ParseError> {
+        let token = self.next_token(what)?;
+        token
+            .text
+            .parse::<u32>()
+            .map_err(|_| token.error(&format!("expected {what}, found '{}'", token.text)))
+    }
+
+    pub fn next_u64(&mut self, what: &str) -> Result<u64, ParseError> {
+        let token = self.next_token(what)?;
+        token
+            .text
+            .parse::<u64>()
+            .map_err(|_| token.error(&format!("expected {what}, found '{}'", token.text)))
+    }
+
+    pub fn next_vec3(&mut self, what: &str) -> Result<Vec3, ParseError> {
+        Ok(Vec3::new(
+            self.next_f32(what)?,
+            self.next_f32(what)?,
+            self.next_f32(what)?,
+        ))
+    }
+
+    // Errors on anything left over after the values a keyword takes
+    pub fn finish(&self) -> Result<(), ParseError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => Err(token.error(&format!("unexpected '{}'", token.text))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Line and column of the error parsing `text` gives
+    fn error_position(text: &str) -> (usize, usize) {
+        match parse_scene(text, Path::new("")) {
+            Ok(_) => panic!("expected an error for {text:?}"),
+            Err(err) => (err.line, err.column),
+        }
+    }
+
+    #[test]
+    fn reports_unknown_keywords() {
+        assert_eq!(error_position("samples 4\n  sampels 4\n"), (2, 3));
+        // Known keywords in the wrong block are just as unexpected
+        assert_eq!(error_position("camera\n    vertex 0 0 0\nend\n"), (2, 5));
+    }
+
+    #[test]
+    fn reports_missing_and_invalid_numbers() {
+        // Missing values are reported just past the end of the line
+        assert_eq!(error_position("image 640\n"), (1, 10));
+        assert_eq!(error_position("# comment\nsky 1 1 1 0.5 x 1\n"), (2, 15));
+        assert_eq!(error_position("bounces 40000\n"), (1, 9));
+        assert_eq!(error_position("samples 3000000000\n"), (1, 9));
+        assert_eq!(error_position("samples 0\n"), (1, 9));
+    }
+
+    #[test]
+    fn reports_unterminated_blocks_where_they_start() {
+        assert_eq!(error_position("samples 4\n  camera\n    fov 40\n"), (2, 3));
+        assert_eq!(error_position("end\n"), (1, 1));
+    }
+
+    #[test]
+    fn reports_invalid_cameras() {
+        assert_eq!(error_position("camera\n  fov 0\nend\n"), (2, 7));
+        assert_eq!(error_position("camera\n  fov 180\nend\n"), (2, 7));
+        assert_eq!(error_position("camera\n  fov -30\nend\n"), (2, 7));
+        assert!(parse_scene("camera\n  fov 179.5\nend\n", Path::new("")).is_ok());
+
+        assert_eq!(error_position("camera\n  aperture -0.1\nend\n"), (2, 12));
+        assert_eq!(error_position("camera\n  focus_distance 0\nend\n"), (2, 18));
+        assert!(
+            parse_scene(
+                "camera\n  aperture 0\n  focus_distance 2\nend\n",
+                Path::new("")
+            )
+            .is_ok()
+        );
+
+        // Reported where the camera starts, since either keyword may come last
+        assert_eq!(
+            error_position("image 4 4\n  camera\n    look_at 0 1 2\n    position 0 1 2\n  end\n"),
+            (2, 3)
+        );
+    }
+
+    #[test]
+    fn reports_invalid_shapes() {
+        let material = "material m lambertian 1 1 1\n";
+        assert_eq!(
+            error_position(&format!("{material}sphere 0 0 0 -1 m\n")),
+            (2, 14)
+        );
+        assert_eq!(
+            error_position(&format!("{material}quad 0 0 0  1 0 0  2 0 0 m\n")),
+            (2, 13)
+        );
+        assert_eq!(
+            error_position(&format!("{material}sphere 0 0 0 1 missing\n")),
+            (2, 16)
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    bbox::Bbox,
+    hittable::{HitInfo, Hittable},
+    light::LightShape,
+    material::Material,
+    ray::{Interval, Point3, Ray},
+    vec3::Vec3,
+};
+
+// Analytic sphere, perfectly round however close the camera gets
+pub struct Sphere {
+    center: Point3,
+    radius: f32,
+    material: Material,
+}
+
+impl Sphere {
+    pub fn new(center: Point3, radius: f32, material: Material) -> Sphere {
+        Sphere {
+            center,
+            radius,
+            material,
+        }
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit<'a>(&'a self, ray: Ray, interval: Interval, hit_info_out: &mut HitInfo<'a>) -> bool {
+        // |origin + t dir - center|^2 = radius^2, with b halved
+        let oc = ray.origin() - self.center;
+        let a = ray.dir().dot(ray.dir());
+        let half_b = oc.dot(ray.dir());
+        let c = oc.dot(oc) - self.radius * self.radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 || a == 0.0 {
+            return false;
+        }
+
+        // The nearer root unless it's out of range, then the further one for rays from inside
+        let sqrt_d = discriminant.sqrt();
+        let Some(t) = [(-half_b - sqrt_d) / a, (-half_b + sqrt_d) / a]
+            .into_iter()
+            .find(|&t| interval.contains(t) && t <= hit_info_out.t)
+        else {
+            return false;
+        };
+
+        let point = ray.at(t);
+        let outward_normal = (point - self.center) / self.radius;
+
+        hit_info_out.front_face = ray.dir().dot(outward_normal) < 0.0;
+        hit_info_out.normal = if hit_info_out.front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+        hit_info_out.shading_normal = hit_info_out.normal;
+        hit_info_out.barycentric = [0.0, 0.0];
+        hit_info_out.t = t;
+        hit_info_out.point = point;
+        hit_info_out.material = &self.material;
+
+        true
+    }
+
+    fn bounds(&self) -> Bbox {
+        let axis = |center: f32| Interval::new(center - self.radius, center + self.radius);
+
+        Bbox::new(
+            axis(self.center.x),
+            axis(self.center.y),
+            axis(self.center.z),
+        )
+    }
+
+    fn lights(&self) -> Vec<(LightShape, Vec3)> {
+        if self.material.emission.magnitude() <= 0.0 {
+            return Vec::new();
+        }
+
+        let shape = LightShape::Sphere {
+            center: self.center,
+            radius: self.radius,
+        };
+        vec![(shape, self.material.emission)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-5, "{a:?} != {b:?}");
+    }
+
+    fn unit_sphere() -> Sphere {
+        Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Material::lambertian(Vec3::new(0.5, 0.5, 0.5)),
+        )
+    }
+
+    fn hit(sphere: &Sphere, ray: Ray, interval: Interval) -> Option<HitInfo<'_>> {
+        let mut hit_info = HitInfo {
+            t: f32::INFINITY,
+            ..Default::default()
+        };
+        sphere.hit(ray, interval, &mut hit_info).then_some(hit_info)
+    }
+
+    #[test]
+    fn hits_from_outside_and_inside() {
+        let sphere = unit_sphere();
+        let everything = Interval::new(0.001, f32::INFINITY);
+
+        let outside = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_info = hit(&sphere, outside, everything).unwrap();
+        assert!((hit_info.t - 4.0).abs() < 1e-5);
+        assert!(hit_info.front_face);
+        assert_close(hit_info.point, Point3::new(0.0, 0.0, 1.0));
+        assert_close(hit_info.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_close(hit_info.shading_normal, hit_info.normal);
+        assert!(std::ptr::eq(hit_info.material, &sphere.material));
+
+        // From the center the far side is hit, with the normal turned inwards to face the ray
+        let inside = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_info = hit(&sphere, inside, everything).unwrap();
+        assert!((hit_info.t - 1.0).abs() < 1e-5);
+        assert!(!hit_info.front_face);
+        assert_close(hit_info.point, Point3::new(0.0, 0.0, -1.0));
+        assert_close(hit_info.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        // t is in units of the direction, which doesn't have to be normalized
+        let long = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        assert!((hit(&sphere, long, everything).unwrap().t - 2.0).abs() < 1e-5);
+
+        let beside = Ray::new(Point3::new(1.5, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(hit(&sphere, beside, everything).is_none());
+        let away = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(hit(&sphere, away, everything).is_none());
+    }
+
+    #[test]
+    fn only_hits_within_the_interval() {
+        let sphere = unit_sphere();
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        // Entering at t = 4 and leaving at t = 6
+        assert!(hit(&sphere, ray, Interval::new(0.001, 3.9)).is_none());
+        let far = hit(&sphere, ray, Interval::new(4.5, f32::INFINITY)).unwrap();
+        assert!((far.t - 6.0).abs() < 1e-5);
+        assert!(!far.front_face);
+        assert!(hit(&sphere, ray, Interval::new(6.5, f32::INFINITY)).is_none());
+
+        // Nor behind something closer that was already found
+        let mut hit_info = HitInfo {
+            t: 3.0,
+            ..Default::default()
+        };
+        assert!(!sphere.hit(ray, Interval::new(0.001, f32::INFINITY), &mut hit_info));
+        assert_eq!(hit_info.t, 3.0);
+    }
+
+    #[test]
+    fn bounds_and_lights() {
+        let sphere = Sphere::new(
+            Point3::new(1.0, 2.0, 3.0),
+            0.5,
+            Material::emissive(Vec3::new(2.0, 2.0, 2.0)),
+        );
+
+        let bounds = sphere.bounds();
+        for (axis, center) in [1.0, 2.0, 3.0].into_iter().enumerate() {
+            let interval = bounds.axis_interval(axis);
+            assert_eq!(interval.get_val(0), center - 0.5);
+            assert_eq!(interval.get_val(1), center + 0.5);
+        }
+
+        let lights = sphere.lights();
+        assert_eq!(lights.len(), 1);
+        let LightShape::Sphere { center, radius } = lights[0].0 else {
+            panic!("not a sphere");
+        };
+        assert_close(center, Point3::new(1.0, 2.0, 3.0));
+        assert_eq!(radius, 0.5);
+        assert_close(lights[0].1, Vec3::new(2.0, 2.0, 2.0));
+
+        assert!(unit_sphere().lights().is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+// Affine transforms, kept together with their inverse so moving rays into an object's space and
+// hits back out costs no matrix inversion.
+
+use crate::{
+    matrix::{Mat3, Mat4},
+    quat::Quat,
+    ray::Point3,
+    vec3::Vec3,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            matrix: Mat4::IDENTITY,
+            inverse: Mat4::IDENTITY,
+        }
+    }
+}
+
+impl Transform {
+    // `linear` followed by `translation`, None when `linear` can't be inverted
+    pub fn from_affine(linear: Mat3, translation: Vec3) -> Option<Transform> {
+        let inverse = linear.inverse()?;
+
+        Some(Transform {
+            matrix: Mat4::from_affine(linear, translation),
+            inverse: Mat4::from_affine(inverse, -inverse.transform_vector(translation)),
+        })
+    }
+
+    // Rotations are orthogonal, so the inverse is the transpose
+    fn from_rotation(rotation: Mat3, translation: Vec3) -> Transform {
+        let inverse = rotation.transpose();
+
+        Transform {
+            matrix: Mat4::from_affine(rotation, translation),
+            inverse: Mat4::from_affine(inverse, -inverse.transform_vector(translation)),
+        }
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        Transform {
+            matrix: Mat4::from_affine(Mat3::IDENTITY, offset),
+            inverse: Mat4::from_affine(Mat3::IDENTITY, -offset),
+        }
+    }
+
+    // Every factor has to be non zero for the transform to be invertible
+    pub fn scale(factors: Vec3) -> Transform {
+        let matrix = |s: Vec3| {
+            Mat4::from_affine(
+                Mat3::from_cols(
+                    Vec3::new(s.x, 0.0, 0.0),
+                    Vec3::new(0.0, s.y, 0.0),
+                    Vec3::new(0.0, 0.0, s.z),
+                ),
+                Vec3::default(),
+            )
+        };
+
+        Transform {
+            matrix: matrix(factors),
+            inverse: matrix(Vec3::new(1.0 / factors.x, 1.0 / factors.y, 1.0 / factors.z)),
+        }
+    }
+
+    // Counterclockwise looking down `axis` towards the origin
+    pub fn rotate(axis: Vec3, degrees: f32) -> Transform {
+        Transform::from_quat(Quat::from_axis_angle(axis, degrees))
+    }
+
+    pub fn from_quat(rotation: Quat) -> Transform {
+        Transform::from_rotation(rotation.normalized().to_mat3(), Vec3::default())
+    }
+
+    // Moves the origin to `position` and turns -z towards `target`, with +y as close to `up` as
+    // it gets. The same frame the camera looks through. None when the direction is zero or
+    // parallel to `up`
+    pub fn look_at(position: Point3, target: Point3, up: Vec3) -> Option<Transform> {
+        let w = position - target;
+        let u = up.cross(w);
+        if w.magnitude() == 0.0 || u.magnitude() == 0.0 {
+            return None;
+        }
+
+        let w = w / w.magnitude();
+        let u = u / u.magnitude();
+        let v = w.cross(u);
+
+        Some(Transform::from_rotation(Mat3::from_cols(u, v, w), position))
+    }
+
+    // This transform followed by `next`
+    pub fn then(&self, next: Transform) -> Transform {
+        Transform {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn point(&self, p: Point3) -> Point3 {
+        self.matrix.transform_point(p)
+    }
+
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        self.matrix.transform_vector(v)
+    }
+
+    // Normals stay perpendicular to the surface by going through the inverse transpose. The
+    // result isn't normalized
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        self.normal_matrix().transform_vector(n)
+    }
+
+    // For transforming many normals at once
+    pub fn normal_matrix(&self) -> Mat3 {
+        self.inverse.linear().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-4, "{a:?} != {b:?}");
+    }
+
+    fn example() -> Transform {
+        Transform::scale(Vec3::new(2.0, 0.5, 3.0))
+            .then(Transform::rotate(Vec3::new(1.0, 1.0, 0.0), 50.0))
+            .then(Transform::translate(Vec3::new(-1.0, 4.0, 2.0)))
+    }
+
+    #[test]
+    fn applies_in_statement_order() {
+        let t = Transform::translate(Vec3::new(1.0, 0.0, 0.0))
+            .then(Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0))
+            .then(Transform::scale(Vec3::new(2.0, 2.0, 2.0)));
+
+        assert_close(
+            t.point(Point3::new(0.0, 0.0, 0.0)),
+            Point3::new(0.0, 2.0, 0.0),
+        );
+        assert_close(t.vector(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let t = example();
+        let p = Point3::new(0.3, -2.0, 5.0);
+
+        assert_close(t.inverse().point(t.point(p)), p);
+        assert_close(t.point(t.inverse().point(p)), p);
+
+        // Building the inverse from the matrix gives the same thing as composing inverses
+        let m = t.matrix;
+        let translation = Vec3::new(m.rows[0][3], m.rows[1][3], m.rows[2][3]);
+        let from_affine = Transform::from_affine(m.linear(), translation).unwrap();
+        assert_close(from_affine.inverse().point(p), t.inverse().point(p));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let t = example();
+        let (a, b) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 1.0));
+        let n = a.cross(b);
+
+        let n_world = t.normal(n);
+        assert!(n_world.dot(t.vector(a)).abs() < 1e-4);
+        assert!(n_world.dot(t.vector(b)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn from_quat_normalizes_the_rotation() {
+        let q = Quat::from_axis_angle(Vec3::new(-1.0, 0.5, 2.0), 75.0);
+        // Not unit length, which from_quat normalizes away
+        let scaled = Quat {
+            x: 3.0 * q.x,
+            y: 3.0 * q.y,
+            z: 3.0 * q.z,
+            w: 3.0 * q.w,
+        };
+        let t = Transform::from_quat(scaled);
+        let v = Vec3::new(0.4, -1.5, 2.0);
+
+        assert_close(t.vector(v), q.to_mat3().transform_vector(v));
+        let back = Quat::from_axis_angle(Vec3::new(-1.0, 0.5, 2.0), -75.0);
+        assert_close(t.inverse().vector(v), back.to_mat3().transform_vector(v));
+    }
+
+    #[test]
+    fn look_at_turns_minus_z_towards_the_target() {
+        let position = Point3::new(1.0, 2.0, 3.0);
+        let target = Point3::new(4.0, 2.0, -1.0);
+        let t = Transform::look_at(position, target, Vec3::new(0.0, 1.0, 0.0)).unwrap();
+
+        assert_close(t.point(Point3::new(0.0, 0.0, 0.0)), position);
+        assert_close(t.point(Point3::new(0.0, 0.0, -5.0)), target);
+        assert_close(t.vector(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+
+        assert!(Transform::look_at(position, position, Vec3::new(0.0, 1.0, 0.0)).is_none());
+        assert!(Transform::look_at(position, target, target - position).is_none());
+    }
+}
@@ -1,17 +1,14 @@
 use core::f32;
-use std::{
-    arch::x86_64::{
-        __m128, _mm_cmple_ps, _mm_max_ps, _mm_min_ps, _mm_movemask_ps, _mm_mul_ps, _mm_set1_ps,
-        _mm_sub_ps,
-    },
-    simd,
-};
+use std::{collections::HashMap, sync::OnceLock};
 
 use crate::{
     bbox::Bbox,
+    bvh::BvhTree,
     hittable::{HitInfo, Hittable},
+    light::{LightList, LightShape},
     material::Material,
     ray::{Interval, Point3, Ray},
+    transform::Transform,
     vec3::Vec3,
 };
 
@@ -19,134 +16,102 @@ pub struct TriangleMesh {
     indices: Vec<u32>,
     vertices: Vec<Point3>,
     normals: Vec<Vec3>,
+    // Optional per vertex attributes, either empty or holding one entry per vertex
+    vertex_normals: Vec<Vec3>,
+    texcoords: Vec<[f32; 2]>,
     material: Material,
+    bvh: OnceLock<BvhTree>,
+}
+
+// Background that lights rays escaping the scene, blending from horizon to zenith
+#[derive(Clone, Copy)]
+pub struct Sky {
+    pub horizon: Vec3,
+    pub zenith: Vec3,
+}
+
+impl Default for Sky {
+    fn default() -> Sky {
+        Sky {
+            horizon: Vec3::new(1.0, 1.0, 1.0),
+            zenith: Vec3::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Sky {
+    pub fn color(&self, dir: Vec3) -> Vec3 {
+        let dir_u = dir / dir.magnitude();
+        let a = 0.5 * (dir_u.y + 1.0);
+
+        (1.0 - a) * self.horizon + a * self.zenith
+    }
 }
 
 #[derive(Default)]
 pub struct Scene {
-    meshes: Vec<TriangleMesh>,
+    objects: Vec<Box<dyn Hittable + Send + Sync>>,
+    // Bounds of each object, in the same order
     nodes: Vec<Bbox>,
-    bounds: [[simd::f32x4; 2]; 3],
+    top_level: OnceLock<BvhTree>,
+    lights: OnceLock<LightList>,
+    sky: Sky,
 }
 
 impl Scene {
-    pub fn add_mesh(&mut self, mesh: TriangleMesh) {
-        let mut min_x: f32 = f32::INFINITY;
-        let mut min_y: f32 = f32::INFINITY;
-        let mut min_z: f32 = f32::INFINITY;
-
-        let mut max_x: f32 = f32::NEG_INFINITY;
-        let mut max_y: f32 = f32::NEG_INFINITY;
-        let mut max_z: f32 = f32::NEG_INFINITY;
-
-        for vertex in &mesh.vertices {
-            min_x = f32::min(min_x, vertex.x);
-            max_x = f32::max(max_x, vertex.x);
+    pub fn set_sky(&mut self, sky: Sky) {
+        self.sky = sky;
+    }
 
-            min_y = f32::min(min_y, vertex.y);
-            max_y = f32::max(max_y, vertex.y);
+    pub fn sky(&self) -> &Sky {
+        &self.sky
+    }
 
-            min_z = f32::min(min_z, vertex.z);
-            max_z = f32::max(max_z, vertex.z);
-        }
+    pub fn add_mesh(&mut self, mesh: TriangleMesh) {
+        // Build the mesh's bvh up front instead of on the first ray that reaches it
+        mesh.bvh();
+        self.add(mesh);
+    }
 
-        let epsilon: f32 = 0.0001;
+    pub fn add(&mut self, object: impl Hittable + Send + Sync + 'static) {
+        self.nodes.push(object.bounds());
+        self.objects.push(Box::new(object));
 
-        self.nodes.push(Bbox::new(
-            Interval::new(min_x - epsilon, max_x + epsilon),
-            Interval::new(min_y - epsilon, max_y + epsilon),
-            Interval::new(min_z - epsilon, max_z + epsilon),
-        ));
-        self.meshes.push(mesh);
-
-        // build simd data
-        if self.meshes.len() == 4 {
-            for i in 0..4 {
-                self.bounds[0][0].as_mut_array()[i] = self.nodes[i].axis_interval(0).get_val(0);
-                self.bounds[0][1].as_mut_array()[i] = self.nodes[i].axis_interval(0).get_val(1);
-                self.bounds[1][0].as_mut_array()[i] = self.nodes[i].axis_interval(1).get_val(0);
-                self.bounds[1][1].as_mut_array()[i] = self.nodes[i].axis_interval(1).get_val(1);
-                self.bounds[2][0].as_mut_array()[i] = self.nodes[i].axis_interval(2).get_val(0);
-                self.bounds[2][1].as_mut_array()[i] = self.nodes[i].axis_interval(2).get_val(1);
-            }
-        }
+        // The top level tree and light list are rebuilt over every object the next time they're
+        // used
+        self.top_level = OnceLock::new();
+        self.lights = OnceLock::new();
     }
 
-    fn simd_intersect(&self, ray: Ray) -> i32 {
-        unsafe {
-            let origin: [__m128; 3] = [
-                _mm_set1_ps(ray.origin().axis_val(0)),
-                _mm_set1_ps(ray.origin().axis_val(1)),
-                _mm_set1_ps(ray.origin().axis_val(2)),
-            ];
-            let dir_inv: [__m128; 3] = [
-                _mm_set1_ps(1.0 / ray.dir().axis_val(0)),
-                _mm_set1_ps(1.0 / ray.dir().axis_val(1)),
-                _mm_set1_ps(1.0 / ray.dir().axis_val(2)),
-            ];
-
-            let signs: [bool; 3] = [
-                ray.dir().axis_val(0).is_sign_negative(),
-                ray.dir().axis_val(1).is_sign_negative(),
-                ray.dir().axis_val(2).is_sign_negative(),
-            ];
-
-            let mut tmin = _mm_set1_ps(0.0);
-            let mut tmax = _mm_set1_ps(f32::INFINITY);
-
-            for i in 0..=2 {
-                let bmin: __m128 = self.bounds[i][signs[i] as usize].into();
-                let bmax: __m128 = self.bounds[i][!signs[i] as usize].into();
-
-                let dmin = _mm_mul_ps(_mm_sub_ps(bmin, origin[i]), dir_inv[i]);
-                let dmax = _mm_mul_ps(_mm_sub_ps(bmax, origin[i]), dir_inv[i]);
-
-                tmin = _mm_max_ps(tmin, dmin);
-                tmax = _mm_min_ps(tmax, dmax);
-            }
+    // Every emissive surface in the scene
+    pub fn lights(&self) -> &LightList {
+        self.lights
+            .get_or_init(|| LightList::new(self.objects.iter().flat_map(|object| object.lights())))
+    }
 
-            let result = _mm_cmple_ps(tmin, tmax);
-            return _mm_movemask_ps(result);
-        }
+    fn top_level(&self) -> &BvhTree {
+        self.top_level.get_or_init(|| BvhTree::new(&self.nodes))
     }
 }
 
 impl Hittable for Scene {
-    fn hit(&self, ray: Ray, interval: Interval, hit_info_out: &mut HitInfo) -> bool {
+    fn hit<'a>(&'a self, ray: Ray, interval: Interval, hit_info_out: &mut HitInfo<'a>) -> bool {
         hit_info_out.t = f32::INFINITY;
 
-        let intersections = self.simd_intersect(ray);
-
-        if intersections & 1 != 0 {
-            self.meshes[0].hit(ray, interval, hit_info_out);
-        }
-        if intersections & 2 != 0 {
-            self.meshes[1].hit(ray, interval, hit_info_out);
-        }
-        if intersections & 4 != 0 {
-            self.meshes[2].hit(ray, interval, hit_info_out);
-        }
-        if intersections & 8 != 0 {
-            self.meshes[3].hit(ray, interval, hit_info_out);
-        }
-
-        if hit_info_out.t < f32::INFINITY {
-            return true;
-        }
+        self.top_level()
+            .traverse(ray, interval, |object, interval| {
+                if self.objects[object].hit(ray, interval, hit_info_out) {
+                    return Some(hit_info_out.t);
+                }
 
-        // while i < self.meshes.len() {
-        //     if self.nodes[i].intersects(ray) {
-        //         self.meshes[i].hit(ray, interval, hit_info_out);
-        //     }
-
-        //     i += 1;
-        // }
-
-        if hit_info_out.t < f32::INFINITY {
-            return true;
-        }
+                None
+            })
+    }
 
-        return false;
+    fn bounds(&self) -> Bbox {
+        self.nodes
+            .iter()
+            .fold(Bbox::empty(), |bounds, node| bounds.union(*node))
     }
 }
 
@@ -156,7 +121,10 @@ impl TriangleMesh {
             indices: Vec::new(),
             vertices: Vec::new(),
             normals: Vec::new(),
-            material: material,
+            vertex_normals: Vec::new(),
+            texcoords: Vec::new(),
+            material,
+            bvh: OnceLock::new(),
         }
     }
 
@@ -164,43 +132,241 @@ impl TriangleMesh {
         self.vertices.push(new_vertex);
     }
 
-    pub fn add_triangle(&mut self, vertex_index_1: u32, vertex_index_2: u32, vertex_index_3: u32) {
-        self.indices.push(vertex_index_1);
-        self.indices.push(vertex_index_2);
-        self.indices.push(vertex_index_3);
+    // Normal of the vertex with the same index, added either for every vertex or none of them
+    pub fn add_vertex_normal(&mut self, normal: Vec3) {
+        self.vertex_normals.push(normal);
+    }
 
-        let a = self.vertices[vertex_index_1 as usize];
-        let b = self.vertices[vertex_index_2 as usize];
-        let c = self.vertices[vertex_index_3 as usize];
+    // Texture coordinate of the vertex with the same index, same rules as vertex normals
+    pub fn add_texcoord(&mut self, texcoord: [f32; 2]) {
+        self.texcoords.push(texcoord);
+    }
+
+    pub fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn has_vertex_normals(&self) -> bool {
+        !self.vertex_normals.is_empty()
+    }
+
+    // Gives every vertex a normal averaged over the faces around it, weighted by their angle at
+    // the vertex. Faces meeting at more than `max_angle` degrees stay a crease: a vertex on it is
+    // split in two, each side only averaging the faces on its side. Replaces any vertex normals
+    // the mesh already had
+    pub fn smooth_normals(&mut self, max_angle: f32) {
+        let cos_max_angle = max_angle.to_radians().cos();
+
+        // Angle of each triangle corner, and every corner at each position. By position rather
+        // than vertex index, so vertices split for texture seams are still smoothed together
+        let key = |v: Vec3| [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()];
+        let mut corner_angles = Vec::with_capacity(self.indices.len());
+        let mut corners_at: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+
+        for (corner, &index) in self.indices.iter().enumerate() {
+            let triangle = corner - corner % 3;
+            let p = self.vertices[index as usize];
+            let next = self.vertices[self.indices[triangle + (corner + 1) % 3] as usize] - p;
+            let prev = self.vertices[self.indices[triangle + (corner + 2) % 3] as usize] - p;
+
+            let cos = next.dot(prev) / (next.magnitude() * prev.magnitude());
+            corner_angles.push(cos.clamp(-1.0, 1.0).acos());
+            corners_at.entry(key(p)).or_default().push(corner);
+        }
+
+        let vertices = std::mem::take(&mut self.vertices);
+        let texcoords = std::mem::take(&mut self.texcoords);
+        self.vertex_normals.clear();
+
+        // Corners of a vertex that end up with the same normal share a vertex again
+        let mut new_indices: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+
+        for corner in 0..self.indices.len() {
+            let index = self.indices[corner];
+            let face_normal = self.normals[corner / 3];
+
+            let mut normal = Vec3::default();
+            for &other in &corners_at[&key(vertices[index as usize])] {
+                let other_normal = self.normals[other / 3];
+                if other_normal.dot(face_normal) >= cos_max_angle {
+                    normal = normal + corner_angles[other] * other_normal;
+                }
+            }
+            // Faces folded back onto each other can cancel out
+            normal = if normal.magnitude() > 0.0 {
+                normal / normal.magnitude()
+            } else {
+                face_normal
+            };
+
+            self.indices[corner] = *new_indices.entry((index, key(normal))).or_insert_with(|| {
+                self.vertices.push(vertices[index as usize]);
+                self.vertex_normals.push(normal);
+                if let Some(texcoord) = texcoords.get(index as usize) {
+                    self.texcoords.push(*texcoord);
+                }
+                self.vertices.len() as u32 - 1
+            });
+        }
+
+        self.bvh = OnceLock::new();
+    }
+
+    pub fn add_triangle(&mut self, vertex_index_1: u32, vertex_index_2: u32, vertex_index_3: u32) {
+        let mut corners = [vertex_index_1, vertex_index_2, vertex_index_3];
+        let [a, b, c] = corners.map(|index| self.vertices[index as usize]);
 
         let mut normal = (b - a).cross(c - a);
         normal = normal / normal.magnitude();
+
+        // Supplied vertex normals decide which side is outside, whatever the winding says
+        if !self.vertex_normals.is_empty() {
+            let [na, nb, nc] = corners.map(|index| self.vertex_normals[index as usize]);
+            if (na + nb + nc).dot(normal) < 0.0 {
+                corners.swap(1, 2);
+                normal = -normal;
+            }
+        }
+
+        self.indices.extend(corners);
         self.normals.push(normal);
+
+        // Any tree built so far no longer covers every triangle
+        self.bvh = OnceLock::new();
+    }
+
+    // Moves the vertices and turns the normals along with them, baking the transform into the
+    // mesh instead of carrying it around like an instance does
+    pub fn transform(&mut self, transform: &Transform) {
+        for vertex in &mut self.vertices {
+            *vertex = transform.point(*vertex);
+        }
+
+        let normal_matrix = transform.normal_matrix();
+        for normal in self.normals.iter_mut().chain(&mut self.vertex_normals) {
+            let n = normal_matrix.transform_vector(*normal);
+            *normal = n / n.magnitude();
+        }
+
+        // A mirroring transform reverses the winding, swap two corners so it agrees with the face
+        // normal again
+        if normal_matrix.determinant() < 0.0 {
+            for triangle in self.indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+
+        self.bvh = OnceLock::new();
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [Point3; 3]> + '_ {
+        self.indices
+            .chunks_exact(3)
+            .map(|tri| [tri[0], tri[1], tri[2]].map(|index| self.vertices[index as usize]))
+    }
+
+    pub fn bvh(&self) -> &BvhTree {
+        self.bvh.get_or_init(|| {
+            let triangle_bounds: Vec<Bbox> = self
+                .triangles()
+                .map(|[a, b, c]| Bbox::from_points(a, b, c))
+                .collect();
+
+            BvhTree::new(&triangle_bounds)
+        })
     }
 }
 
 impl Hittable for TriangleMesh {
-    fn hit(&self, ray: Ray, interval: Interval, hit_info_out: &mut HitInfo) -> bool {
-        let mut i: usize = 0;
-        let mut hit = false;
-
-        while i < self.indices.len() {
+    fn hit<'a>(&'a self, ray: Ray, interval: Interval, hit_info_out: &mut HitInfo<'a>) -> bool {
+        let mut closest = 0;
+        let hit = self.bvh().traverse(ray, interval, |triangle, interval| {
+            let i = triangle * 3;
             let a = self.vertices[self.indices[i] as usize];
             let b = self.vertices[self.indices[i + 1] as usize];
             let c = self.vertices[self.indices[i + 2] as usize];
 
-            let normal = self.normals[i / 3];
+            let normal = self.normals[triangle];
 
-            hit |= triangle_hit(a, b, c, normal, ray, interval, hit_info_out);
+            if triangle_hit(a, b, c, normal, ray, interval, hit_info_out) {
+                closest = triangle;
+                return Some(hit_info_out.t);
+            }
 
-            i += 3;
+            None
+        });
+
+        if !hit {
+            return false;
         }
-        if hit {
-            hit_info_out.material = self.material;
-            return true;
+
+        // Smooth shading, blending the corners' normals
+        if !self.vertex_normals.is_empty() {
+            let i = closest * 3;
+            let [na, nb, nc] =
+                [i, i + 1, i + 2].map(|corner| self.vertex_normals[self.indices[corner] as usize]);
+            let [u, v] = hit_info_out.barycentric;
+
+            let mut normal = (1.0 - u - v) * na + u * nb + v * nc;
+            normal = normal / normal.magnitude();
+            hit_info_out.shading_normal = if hit_info_out.front_face {
+                normal
+            } else {
+                -normal
+            };
         }
 
-        false
+        hit_info_out.material = &self.material;
+        true
+    }
+
+    // Padded, so flat meshes still have some volume for rays to enter
+    fn bounds(&self) -> Bbox {
+        let mut min_x: f32 = f32::INFINITY;
+        let mut min_y: f32 = f32::INFINITY;
+        let mut min_z: f32 = f32::INFINITY;
+
+        let mut max_x: f32 = f32::NEG_INFINITY;
+        let mut max_y: f32 = f32::NEG_INFINITY;
+        let mut max_z: f32 = f32::NEG_INFINITY;
+
+        for vertex in &self.vertices {
+            min_x = f32::min(min_x, vertex.x);
+            max_x = f32::max(max_x, vertex.x);
+
+            min_y = f32::min(min_y, vertex.y);
+            max_y = f32::max(max_y, vertex.y);
+
+            min_z = f32::min(min_z, vertex.z);
+            max_z = f32::max(max_z, vertex.z);
+        }
+
+        let epsilon: f32 = 0.0001;
+
+        Bbox::new(
+            Interval::new(min_x - epsilon, max_x + epsilon),
+            Interval::new(min_y - epsilon, max_y + epsilon),
+            Interval::new(min_z - epsilon, max_z + epsilon),
+        )
+    }
+
+    fn lights(&self) -> Vec<(LightShape, Vec3)> {
+        let emission = self.material.emission;
+        if emission.magnitude() <= 0.0 {
+            return Vec::new();
+        }
+
+        self.triangles()
+            .map(|triangle| (LightShape::Triangle(triangle), emission))
+            .collect()
     }
 }
 
@@ -227,23 +393,182 @@ fn triangle_hit(
 
     let q = ray.at(t);
 
-    if ((b - a).cross(q - a)).dot(normal) < 0.0 {
+    // Twice the areas of the triangles q makes with each edge, each one proportional to the
+    // weight of the corner opposite the edge
+    let area_c = ((b - a).cross(q - a)).dot(normal);
+    if area_c < 0.0 {
         return false;
     }
-    if ((c - b).cross(q - b)).dot(normal) < 0.0 {
+    let area_a = ((c - b).cross(q - b)).dot(normal);
+    if area_a < 0.0 {
         return false;
     }
-    if ((a - c).cross(q - c).dot(normal)) < 0.0 {
+    let area_b = ((a - c).cross(q - c)).dot(normal);
+    if area_b < 0.0 {
         return false;
     }
-    if d < 0.0 {
+    hit_info_out.front_face = d < 0.0;
+    if hit_info_out.front_face {
         hit_info_out.normal = normal;
     } else {
         hit_info_out.normal = -normal;
     }
+    hit_info_out.shading_normal = hit_info_out.normal;
+
+    let area = area_a + area_b + area_c;
+    hit_info_out.barycentric = [area_b / area, area_c / area];
 
     hit_info_out.t = t;
     hit_info_out.point = q;
 
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    // Two triangles folded 40 degrees apart along the x axis, with the same angles at the corners
+    // they share
+    fn folded_mesh() -> TriangleMesh {
+        let (sin, cos) = 40.0_f32.to_radians().sin_cos();
+        let mut mesh = TriangleMesh::new(Material::lambertian(Vec3::new(0.5, 0.5, 0.5)));
+        mesh.add_vertex(Point3::new(0.0, 0.0, 0.0));
+        mesh.add_vertex(Point3::new(1.0, 0.0, 0.0));
+        mesh.add_vertex(Point3::new(0.5, 1.0, 0.0));
+        mesh.add_vertex(Point3::new(0.5, -cos, -sin));
+        mesh.add_triangle(0, 1, 2);
+        mesh.add_triangle(0, 3, 1);
+        mesh
+    }
+
+    fn hit_from_above(mesh: &TriangleMesh, x: f32, y: f32) -> HitInfo<'_> {
+        let mut hit_info = HitInfo {
+            t: f32::INFINITY,
+            ..HitInfo::default()
+        };
+        let ray = Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(mesh.hit(ray, Interval::new(0.001, f32::INFINITY), &mut hit_info));
+        hit_info
+    }
+
+    #[test]
+    fn barycentrics_rebuild_the_hit_point() {
+        let mesh = folded_mesh();
+        let hit_info = hit_from_above(&mesh, 0.4, 0.3);
+
+        let [u, v] = hit_info.barycentric;
+        let point = (1.0 - u - v) * mesh.vertices[0] + u * mesh.vertices[1] + v * mesh.vertices[2];
+        assert!((point - hit_info.point).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn smoothing_splits_vertices_on_creases() {
+        let mut creased = folded_mesh();
+        creased.smooth_normals(30.0);
+        assert_eq!(creased.vertex_count(), 6);
+        let hit_info = hit_from_above(&creased, 0.5, 0.001);
+        assert!(hit_info.shading_normal.dot(hit_info.normal) > 0.9999);
+
+        // Along the shared edge the normal is halfway between the faces
+        let mut smooth = folded_mesh();
+        smooth.smooth_normals(50.0);
+        assert_eq!(smooth.vertex_count(), 4);
+        let hit_info = hit_from_above(&smooth, 0.5, 0.001);
+        let halfway = smooth.normals[0] + smooth.normals[1];
+        assert!(hit_info.shading_normal.dot(halfway / halfway.magnitude()) > 0.9999);
+    }
+
+    // A handful of random triangles around a random center, told apart by their emission
+    fn random_meshes(seed: u64, count: usize) -> Vec<TriangleMesh> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut random_point = |size: f32| {
+            Point3::new(
+                rng.random_range(-size..size),
+                rng.random_range(-size..size),
+                rng.random_range(-size..size),
+            )
+        };
+
+        (0..count)
+            .map(|i| {
+                let mut mesh = TriangleMesh::new(Material::emissive(Vec3::new(i as f32, 0.0, 0.0)));
+                let center = random_point(2.0);
+                for _ in 0..4 {
+                    let first = mesh.vertex_count() as u32;
+                    for _ in 0..3 {
+                        mesh.add_vertex(center + random_point(0.7));
+                    }
+                    mesh.add_triangle(first, first + 1, first + 2);
+                }
+                mesh
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scene_finds_the_nearest_hit_of_all_meshes() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let interval = Interval::new(0.001, f32::INFINITY);
+
+        for count in [1, 2, 3, 5, 8, 40] {
+            let meshes = random_meshes(count as u64, count);
+            let mut scene = Scene::default();
+            for mesh in random_meshes(count as u64, count) {
+                scene.add_mesh(mesh);
+            }
+
+            let mut hits = 0;
+            for _ in 0..2000 {
+                let origin = Point3::new(
+                    rng.random_range(-4.0..4.0),
+                    rng.random_range(-4.0..4.0),
+                    rng.random_range(-4.0..4.0),
+                );
+                // Mostly towards a point on some triangle, the rest anywhere
+                let target = if rng.random_bool(0.8) {
+                    let mesh = &meshes[rng.random_range(0..count)];
+                    let triangles: Vec<_> = mesh.triangles().collect();
+                    let [a, b, c] = triangles[rng.random_range(0..triangles.len())];
+                    let (u, v): (f32, f32) = (rng.random(), rng.random());
+                    let (u, v) = if u + v > 1.0 {
+                        (1.0 - u, 1.0 - v)
+                    } else {
+                        (u, v)
+                    };
+                    a + u * (b - a) + v * (c - a)
+                } else {
+                    Point3::default()
+                };
+                let ray = Ray::new(origin, target - origin);
+
+                // Brute force, every mesh on its own
+                let expected = meshes
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, mesh)| {
+                        let mut hit_info = HitInfo {
+                            t: f32::INFINITY,
+                            ..HitInfo::default()
+                        };
+                        mesh.hit(ray, interval, &mut hit_info)
+                            .then_some((hit_info.t, i))
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0));
+
+                let mut hit_info = HitInfo::default();
+                let hit = scene.hit(ray, interval, &mut hit_info);
+                assert_eq!(hit, expected.is_some(), "{count} meshes, {ray:?}");
+
+                if let Some((t, i)) = expected {
+                    hits += 1;
+                    assert_eq!(hit_info.t, t, "{count} meshes, {ray:?}");
+                    assert_eq!(hit_info.material.emission.x, i as f32);
+                }
+            }
+            assert!(hits > 1000, "{count} meshes, only {hits} hits");
+        }
+    }
+}
@@ -1,4 +1,3 @@
-use rand::Rng;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
 #[derive(Clone, Copy, Default, Debug)]
@@ -13,31 +12,17 @@ impl Vec3 {
         Vec3 { x, y, z }
     }
 
-    pub fn random() -> Vec3 {
-        Vec3 {
-            x: rand::rng().random(),
-            y: rand::rng().random(),
-            z: rand::rng().random(),
-        }
-    }
-
-    pub fn random_range(min: f32, max: f32) -> Vec3 {
-        Vec3 {
-            x: rand::rng().random_range(min..max),
-            y: rand::rng().random_range(min..max),
-            z: rand::rng().random_range(min..max),
-        }
-    }
-
-    pub fn random_unit() -> Vec3 {
-        let vec = Vec3::random_range(-1., 1.);
-        let mag_squared = vec.dot(vec);
-
-        if 1e-160 < mag_squared && mag_squared <= 1. {
-            return vec / f32::sqrt(mag_squared);
-        }
-
-        vec
+    // Two unit vectors that, together with this unit vector, form an orthonormal basis
+    // (Duff et al. 2017, continuous everywhere except where z changes sign)
+    pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        let sign = f32::copysign(1.0, self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+
+        (
+            Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vec3::new(b, sign + self.y * self.y * a, -self.y),
+        )
     }
 
     pub fn axis_val(&self, i: usize) -> f32 {
@@ -134,6 +119,43 @@ impl Mul<Vec3> for Vec3 {
 impl Div<f32> for Vec3 {
     type Output = Vec3;
     fn div(self, c: f32) -> Vec3 {
-       return self * (1.0/c);
+       self * (1.0/c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::sampling::uniform_sphere;
+
+    fn random_unit(rng: &mut impl Rng) -> Vec3 {
+        uniform_sphere([rng.random(), rng.random()])
+    }
+
+    #[test]
+    fn orthonormal_basis_is_orthonormal() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let axes = [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+        ];
+        let random = (0..10_000).map(|_| random_unit(&mut rng));
+
+        for normal in axes.into_iter().chain(random) {
+            let (tangent, bitangent) = normal.orthonormal_basis();
+
+            assert!((tangent.magnitude() - 1.0).abs() < 1e-4);
+            assert!((bitangent.magnitude() - 1.0).abs() < 1e-4);
+            assert!(tangent.dot(bitangent).abs() < 1e-4);
+            assert!(tangent.dot(normal).abs() < 1e-4);
+            assert!(bitangent.dot(normal).abs() < 1e-4);
+
+            // Right handed, so the frame doesn't mirror what it transforms
+            assert!((tangent.cross(bitangent) - normal).magnitude() < 1e-4);
+        }
     }
 }
@@ -0,0 +1,405 @@
+// Just enough of zlib (RFC 1950) and deflate (RFC 1951) to write compressed image formats.
+// Compression finds repeats with LZ77 over hash chains and codes them with the fixed Huffman
+// tables, which keeps things simple while still shrinking rendered images a lot.
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// How many earlier positions with the same hash are compared before settling for the best so far
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+// Smallest length for each length code from 257, and its number of extra bits
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// Smallest distance for each distance code, and its number of extra bits
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// Compresses `data` into a zlib stream
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window, default compression level, no preset dictionary
+    let mut out = vec![0x78, 0x9c];
+
+    out.extend(deflate(data));
+    out.extend(adler32(data).to_be_bytes());
+
+    out
+}
+
+// CRC-32 as used by PNG and zip (reflected, polynomial 0xedb88320)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffff;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+    crc ^ 0xffffffff
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+
+    // 5552 bytes is the most that can be summed before the u32s could overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_buffer: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    // Writes the low `count` bits of `bits`, least significant first
+    fn write_bits(&mut self, bits: u32, count: u32) {
+        self.bit_buffer |= (bits as u64) << self.bit_count;
+        self.bit_count += count;
+
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bit_buffer as u8);
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting from their most significant bit
+    fn write_code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.write_bits(reversed, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.bytes.push(self.bit_buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// Fixed Huffman code and length for a literal/length symbol
+fn fixed_literal_code(symbol: u32) -> (u32, u32) {
+    match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_literal(writer: &mut BitWriter, byte: u8) {
+    let (code, length) = fixed_literal_code(byte as u32);
+    writer.write_code(code, length);
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let length_idx = LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1;
+    let (code, code_length) = fixed_literal_code(257 + length_idx as u32);
+    writer.write_code(code, code_length);
+    writer.write_bits(
+        (length - LENGTH_BASE[length_idx] as usize) as u32,
+        LENGTH_EXTRA[length_idx] as u32,
+    );
+
+    // Distance codes are all 5 bits long in the fixed code
+    let distance_idx = DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1;
+    writer.write_code(distance_idx as u32, 5);
+    writer.write_bits(
+        (distance - DISTANCE_BASE[distance_idx] as usize) as u32,
+        DISTANCE_EXTRA[distance_idx] as u32,
+    );
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let val = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+    (val.wrapping_mul(0x9e3779b1) >> (32 - HASH_BITS)) as usize
+}
+
+// Raw deflate stream of `data` as a single fixed Huffman block
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        bytes: Vec::with_capacity(data.len() / 2),
+        bit_buffer: 0,
+        bit_count: 0,
+    };
+
+    // BFINAL = 1, BTYPE = 01 (fixed Huffman)
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    // head[hash] is the latest position with that hash, prev[pos % WINDOW_SIZE] the one before it
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (length, distance) = longest_match(data, pos, &head, &prev);
+
+        if length >= MIN_MATCH {
+            write_match(&mut writer, length, distance);
+            for i in pos..pos + length {
+                insert(data, i, &mut head, &mut prev);
+            }
+            pos += length;
+        } else {
+            write_literal(&mut writer, data[pos]);
+            insert(data, pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+
+    // End of block
+    let (code, length) = fixed_literal_code(256);
+    writer.write_code(code, length);
+
+    writer.finish()
+}
+
+// Makes `pos` the latest position in its hash chain
+fn insert(data: &[u8], pos: usize, head: &mut [usize], prev: &mut [usize]) {
+    if pos + MIN_MATCH <= data.len() {
+        let h = hash(data, pos);
+        prev[pos % WINDOW_SIZE] = head[h];
+        head[h] = pos;
+    }
+}
+
+fn longest_match(data: &[u8], pos: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if pos + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+
+    let max_length = usize::min(MAX_MATCH, data.len() - pos);
+    let mut best = (0, 0);
+    let mut candidate = head[hash(data, pos)];
+
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX || pos - candidate > WINDOW_SIZE - 1 {
+            break;
+        }
+
+        let length = data[candidate..candidate + max_length]
+            .iter()
+            .zip(&data[pos..pos + max_length])
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        if length > best.0 {
+            best = (length, pos - candidate);
+            if length == max_length {
+                break;
+            }
+        }
+
+        // Older entries in `prev` may have been overwritten by newer positions in the window
+        let next = prev[candidate % WINDOW_SIZE];
+        if next == usize::MAX || next >= candidate {
+            break;
+        }
+        candidate = next;
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+
+    use super::*;
+    use crate::rng::Pcg32;
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414fa339
+        );
+
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        // Long enough for the sums to wrap around the modulus
+        assert_eq!(adler32(&[0xff; 6000]), 0xa49759ea);
+    }
+
+    // Reads deflate's least significant bit first stream
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bit(&mut self) -> u32 {
+            let bit = (self.bytes[self.pos / 8] >> (self.pos % 8)) & 1;
+            self.pos += 1;
+            bit as u32
+        }
+
+        fn bits(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |value, i| value | self.bit() << i)
+        }
+
+        // Huffman codes, most significant bit first
+        fn code(&mut self, length: u32) -> u32 {
+            (0..length).fold(0, |code, _| code << 1 | self.bit())
+        }
+
+        // Literal/length symbol of the fixed code, whose codes are told apart by their prefixes
+        fn fixed_symbol(&mut self) -> u32 {
+            let mut code = self.code(7);
+            if code <= 0x17 {
+                return 256 + code;
+            }
+            code = code << 1 | self.bit();
+            match code {
+                0x30..=0xbf => code - 0x30,
+                0xc0..=0xc7 => 280 + code - 0xc0,
+                _ => 144 + (code << 1 | self.bit()) - 0x190,
+            }
+        }
+    }
+
+    // Decodes a zlib stream of fixed Huffman blocks, the only kind `deflate` writes, checking the
+    // header and checksum. Also returns the longest match distance used
+    fn inflate(stream: &[u8]) -> (Vec<u8>, usize) {
+        assert_eq!(stream[0] & 0x0f, 8, "not deflate");
+        assert_eq!(
+            u16::from_be_bytes([stream[0], stream[1]]) % 31,
+            0,
+            "bad header check"
+        );
+
+        let mut reader = BitReader {
+            bytes: &stream[2..stream.len() - 4],
+            pos: 0,
+        };
+        let mut data = Vec::new();
+        let mut longest_distance = 0;
+
+        loop {
+            let last = reader.bit() == 1;
+            assert_eq!(reader.bits(2), 1, "not a fixed Huffman block");
+
+            loop {
+                let symbol = reader.fixed_symbol();
+                match symbol {
+                    0..=255 => data.push(symbol as u8),
+                    256 => break,
+                    _ => {
+                        let i = symbol as usize - 257;
+                        let length =
+                            LENGTH_BASE[i] as usize + reader.bits(LENGTH_EXTRA[i] as u32) as usize;
+                        let i = reader.code(5) as usize;
+                        let distance = DISTANCE_BASE[i] as usize
+                            + reader.bits(DISTANCE_EXTRA[i] as u32) as usize;
+
+                        assert!(distance <= data.len(), "match reaches before the start");
+                        longest_distance = usize::max(longest_distance, distance);
+                        // Byte by byte, a match may overlap what it copies
+                        for _ in 0..length {
+                            data.push(data[data.len() - distance]);
+                        }
+                    }
+                }
+            }
+
+            if last {
+                break;
+            }
+        }
+
+        // Only padding may follow the last block
+        assert_eq!(
+            reader.pos.div_ceil(8),
+            reader.bytes.len(),
+            "data after the last block"
+        );
+        let checksum = u32::from_be_bytes(stream[stream.len() - 4..].try_into().unwrap());
+        assert_eq!(checksum, adler32(&data), "checksum doesn't match");
+
+        (data, longest_distance)
+    }
+
+    #[test]
+    fn compresses_known_vectors() {
+        // The same bytes zlib writes for these
+        assert_eq!(
+            zlib_compress(b""),
+            [0x78, 0x9c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]
+        );
+        assert_eq!(
+            zlib_compress(b"a"),
+            [0x78, 0x9c, 0x4b, 0x04, 0x00, 0x00, 0x62, 0x00, 0x62]
+        );
+
+        for data in [&b""[..], b"a", b"\xff"] {
+            assert_eq!(inflate(&zlib_compress(data)).0, data);
+        }
+    }
+
+    #[test]
+    fn long_runs_round_trip() {
+        // Matches overlapping the bytes they copy, and longer than the longest single match
+        for length in [3, 4, 258, 259, 1000, 100_000] {
+            let data = vec![7; length];
+            let compressed = zlib_compress(&data);
+            assert_eq!(inflate(&compressed).0, data);
+        }
+        assert!(zlib_compress(&[7; 100_000]).len() < 1000);
+
+        // Every byte value, with the 9 bit literal codes, repeated
+        let data: Vec<u8> = (0..=255).cycle().take(5000).collect();
+        assert_eq!(inflate(&zlib_compress(&data)).0, data);
+    }
+
+    #[test]
+    fn long_distance_matches_round_trip() {
+        let mut rng = Pcg32::new(9, 0);
+        let mut random = |length: usize| -> Vec<u8> {
+            let mut bytes = vec![0; length];
+            rng.fill_bytes(&mut bytes);
+            bytes
+        };
+
+        // A block repeated after random bytes, as far back as the window reaches and just too far
+        let block = random(500);
+        for gap in [30_000, WINDOW_SIZE - 1 - 500, WINDOW_SIZE - 500] {
+            let data = [&block[..], &random(gap), &block[..]].concat();
+            let (decoded, longest_distance) = inflate(&zlib_compress(&data));
+
+            assert_eq!(decoded, data);
+            if gap + 500 < WINDOW_SIZE {
+                assert_eq!(longest_distance, gap + 500);
+            } else {
+                assert!(longest_distance < WINDOW_SIZE);
+            }
+        }
+    }
+}